@@ -0,0 +1,80 @@
+//! Appends a large log to `Lines` and measures how long appending and
+//! producing one screen of visual lines take, without and with soft
+//! wrap.
+//!
+//! `cargo bench --bench append_lines`
+use cozy_floem::{
+    ansi_to_style::{TextStyle, TextWithStyle},
+    views::{
+        panel::{DocStyle, ErrLevel, Lines, TextSrc},
+        tree_with_panel::data::{Level, StyledText}
+    }
+};
+use doc::lines::line_ending::LineEnding;
+use floem::{kurbo::Rect, peniko::Color};
+use std::time::Instant;
+
+const LINES: usize = 500_000;
+
+fn main() -> anyhow::Result<()> {
+    bench(None)?;
+    // wrapped rows are estimated while appending and measured when
+    // visible, the first screen pays for shaping
+    bench(Some(300.0))
+}
+
+fn bench(wrap_width: Option<f32>) -> anyhow::Result<()> {
+    println!("wrap width {wrap_width:?}");
+    let mut lines = Lines::new(DocStyle::default());
+    lines.set_wrap_width(wrap_width);
+
+    let start = Instant::now();
+    for index in 0..LINES {
+        let text = format!(
+            "[{index:>6}] warning: unused variable: `x` --> \
+             src/main.rs:{}:9\n",
+            index % 1000 + 1
+        );
+        let styles = vec![TextStyle {
+            range:     9..16,
+            bold:      true,
+            italic:    false,
+            underline: false,
+            bg_color:  None,
+            fg_color:  Some(Color::from_rgb8(0xff, 0xbb, 0x00))
+        }];
+        let text = StyledText {
+            id:          TextSrc::StdErr {
+                level: ErrLevel::Other
+            },
+            level:       Level::Warn,
+            styled_text: TextWithStyle { text, styles },
+            hyperlink:   vec![],
+            decorations: vec![]
+        };
+        lines.append_lines(text.to_lines()?, LineEnding::Lf)?;
+    }
+    println!("append {LINES} lines: {:?}", start.elapsed());
+
+    let height = lines.line_info()?.height();
+    for (name, y) in [
+        ("top", 0.0),
+        ("middle", height / 2.0),
+        ("bottom", height - 600.0)
+    ] {
+        let viewport = Rect::new(0.0, y, 800.0, y + 600.0);
+        let start = Instant::now();
+        lines.measure_rows(viewport)?;
+        let visual = lines.visual_lines(viewport, None)?;
+        let first = start.elapsed();
+        let start = Instant::now();
+        lines.visual_lines(viewport, None)?;
+        println!(
+            "visual lines ({name}, {} lines): first {first:?}, \
+             cached {:?}",
+            visual.len(),
+            start.elapsed()
+        );
+    }
+    Ok(())
+}
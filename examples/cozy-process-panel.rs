@@ -0,0 +1,75 @@
+use cozy_floem::{
+    process::{ProcessRunner, Stream, classify_by_keyword},
+    views::{
+        panel::DocStyle,
+        tree_with_panel::{
+            data::{Level, TreePanelData},
+            tree_with_panel
+        }
+    }
+};
+use floem::{
+    Application, View,
+    keyboard::{Key, NamedKey},
+    kurbo::Point,
+    prelude::Decorators,
+    reactive::Scope,
+    window::WindowConfig
+};
+use log::LevelFilter::Info;
+
+fn main() -> anyhow::Result<()> {
+    let _ = custom_utils::logger::logger_feature(
+        "panel",
+        "warn,cozy_floem=debug,cozy_process_panel=debug",
+        Info,
+        false
+    )
+    .build();
+
+    let cx = Scope::new();
+    let data = TreePanelData::new(cx, DocStyle::default());
+    // `tests/test_x.py::TestX::test_a PASSED` is the node `test_a`
+    // under `TestX` under `tests/test_x.py`
+    let runner = ProcessRunner::new("pytest", "pytest")
+        .args(["-v", "--color=yes"])
+        .classify(|line: &str, stream: Stream| {
+            let (level, _) = classify_by_keyword(line, stream);
+            let level = if line.contains(" FAILED") {
+                Level::Error
+            } else if line.contains(" PASSED") {
+                Level::Success
+            } else {
+                level
+            };
+            let tag = line
+                .contains("::")
+                .then(|| line.split_whitespace().next())
+                .flatten()
+                .map(str::to_string);
+            (level, tag)
+        });
+    data.run_process(runner);
+    // runs side by side, with a node of its own
+    data.run_process(
+        ProcessRunner::new("ruff", "ruff").args(["check", "."])
+    );
+    let config =
+        WindowConfig::default().position(Point::new(300.0, 300.));
+    Application::new()
+        .window(move |_| app_view(data), Some(config))
+        .run();
+    Ok(())
+}
+
+fn app_view(data: TreePanelData) -> impl View {
+    let view =
+        tree_with_panel(data).style(|x| x.height(300.0).width(800.0));
+    let id = view.id();
+
+    view.on_key_up(
+        Key::Named(NamedKey::F11),
+        |m| m.is_empty(),
+        move |_| id.inspect()
+    )
+}
@@ -2,7 +2,10 @@ use ansi_to_style::TextStyle;
 use cozy_floem::{
     channel::ExtChannel,
     views::{
-        panel::{DocManager, DocStyle, ErrLevel, TextSrc, panel},
+        panel::{
+            Decoration, DocManager, DocStyle, ErrLevel, GutterStyle,
+            PanelTheme, TextSrc, Timestamp, panel
+        },
         tree_with_panel::data::{Level, StyledText, TreePanelData}
     }
 };
@@ -27,7 +30,15 @@ fn main() -> anyhow::Result<()> {
     .build();
 
     let cx = Scope::new();
-    let data = TreePanelData::new(cx, DocStyle::default());
+    let style = DocStyle {
+        gutter: GutterStyle {
+            line_number: true,
+            timestamp:   Some(Timestamp::Relative),
+            level:       true
+        },
+        ..DocStyle::default()
+    };
+    let data = TreePanelData::new(cx, style);
     data.run_with_async_task(init_content);
     floem::launch(move || app_view(data.doc));
     Ok(())
@@ -41,6 +52,20 @@ fn app_view(simple_doc: DocManager) -> impl View {
         |m| m.is_empty(),
         move |_| id.inspect()
     )
+    .on_key_up(
+        Key::Named(NamedKey::F10),
+        |m| m.is_empty(),
+        move |_| {
+            simple_doc.update(|x| {
+                let theme = if x.style.theme == PanelTheme::dark() {
+                    PanelTheme::light()
+                } else {
+                    PanelTheme::dark()
+                };
+                x.set_theme(theme)
+            })
+        }
+    )
 }
 
 async fn init_content(
@@ -86,10 +111,26 @@ async fn init_content(
                     fg_color:  Some(Color::rgba8(214, 214, 51, 255))
                 }]
             },
-            hyperlink:   vec![]
+            hyperlink:   vec![],
+            decorations: decorations(i)
         };
         channel.send(line);
         tokio::time::sleep(Duration::from_millis(800)).await;
     }
     Ok(())
 }
+
+/// a larger header after a rule every 5 lines, a snippet every 7
+fn decorations(i: usize) -> Vec<Decoration> {
+    let mut decorations = vec![];
+    if i % 5 == 0 {
+        decorations.push(Decoration::Separator);
+        decorations.push(Decoration::Header(1.4));
+    }
+    if i % 7 == 6 {
+        decorations.push(Decoration::Snippet(
+            "fn main() {\n    println!(\"hello\");\n}".to_string()
+        ));
+    }
+    decorations
+}
@@ -1,4 +1,6 @@
+pub mod cargo;
 pub mod channel;
+pub mod process;
 pub mod views;
 
 pub mod ansi_to_style {
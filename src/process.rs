@@ -0,0 +1,282 @@
+use crate::{
+    channel::ExtChannel,
+    views::{
+        panel::{Linkifier, TextSrc},
+        tree_with_panel::data::{Level, StyledText}
+    }
+};
+use ansi_to_style::parse_byte;
+use anyhow::{Result, bail};
+use log::{error, info};
+use std::{path::PathBuf, process::Stdio, sync::Arc};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncRead, BufReader},
+    process::Command,
+    sync::mpsc
+};
+
+/// The pipe a line was read from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Stream {
+    StdOut,
+    StdErr
+}
+
+/// The level of a line, without ansi escapes, and the tag it is
+/// grouped under in the tree.
+pub type Classify = Arc<
+    dyn Fn(&str, Stream) -> (Level, Option<String>) + Send + Sync
+>;
+
+/// Turns a line into a `StyledText` instead of `Classify` and the
+/// linkifier, e.g. `cargo::resolve`. `None` drops the line.
+pub type Resolve =
+    Arc<dyn Fn(&[u8], Stream) -> Option<StyledText> + Send + Sync>;
+
+/// Runs any command, e.g. `npm`, `make` or `pytest`, and sends every
+/// output line as a `StyledText` with a `TextSrc::Process` source.
+#[derive(Clone)]
+pub struct ProcessRunner {
+    pub name:    String,
+    program:     String,
+    args:        Vec<String>,
+    current_dir: Option<PathBuf>,
+    classify:    Classify,
+    linkifier:   Linkifier,
+    resolve:     Option<Resolve>
+}
+
+impl ProcessRunner {
+    /// `name` is shown in the tree and the panel
+    pub fn new(
+        name: impl Into<String>,
+        program: impl Into<String>
+    ) -> Self {
+        Self {
+            name:        name.into(),
+            program:     program.into(),
+            args:        vec![],
+            current_dir: None,
+            classify:    Arc::new(classify_by_keyword),
+            linkifier:   Linkifier::default(),
+            resolve:     None
+        }
+    }
+
+    pub fn args(
+        mut self,
+        args: impl IntoIterator<Item = impl Into<String>>
+    ) -> Self {
+        self.args.extend(args.into_iter().map(Into::into));
+        self
+    }
+
+    pub fn current_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.current_dir = Some(dir.into());
+        self
+    }
+
+    pub fn classify(
+        mut self,
+        classify: impl Fn(&str, Stream) -> (Level, Option<String>)
+        + Send
+        + Sync
+        + 'static
+    ) -> Self {
+        self.classify = Arc::new(classify);
+        self
+    }
+
+    pub fn linkifier(mut self, linkifier: Linkifier) -> Self {
+        self.linkifier = linkifier;
+        self
+    }
+
+    pub fn resolve(
+        mut self,
+        resolve: impl Fn(&[u8], Stream) -> Option<StyledText>
+        + Send
+        + Sync
+        + 'static
+    ) -> Self {
+        self.resolve = Some(Arc::new(resolve));
+        self
+    }
+
+    /// Run the command to its end, an error if it doesn't exit with
+    /// success or its output can't be read
+    pub async fn run(
+        &self,
+        mut channel: ExtChannel<StyledText>
+    ) -> Result<()> {
+        let mut command = Command::new(&self.program);
+        command.args(&self.args);
+        if let Some(dir) = &self.current_dir {
+            command.current_dir(dir);
+        }
+        let mut child = command
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+
+        let (tx, mut rx) = mpsc::channel(100);
+        let mut readers = vec![];
+        if let Some(stdout) = child.stdout.take() {
+            let tx = tx.clone();
+            readers.push(tokio::spawn(read_lines(
+                stdout,
+                Stream::StdOut,
+                tx
+            )));
+        }
+        if let Some(stderr) = child.stderr.take() {
+            let tx = tx.clone();
+            readers.push(tokio::spawn(read_lines(
+                stderr,
+                Stream::StdErr,
+                tx
+            )));
+        }
+        drop(tx);
+        while let Some((line, stream)) = rx.recv().await {
+            let text = match &self.resolve {
+                Some(resolve) => resolve(&line, stream),
+                None => Some(self.styled_text(&line, stream))
+            };
+            if let Some(text) = text {
+                channel.send(text);
+            }
+        }
+        let mut read_error = None;
+        for reader in readers {
+            if let Err(err) = reader.await? {
+                read_error.get_or_insert(err);
+            }
+        }
+        if let Some(err) = read_error {
+            // nobody reads its output anymore
+            child.start_kill()?;
+            child.wait().await?;
+            let line = format!("{} output: {err}", self.name);
+            self.send_error(&mut channel, &line);
+            bail!(line);
+        }
+
+        let status = child.wait().await?;
+        info!("{} done: {status}", self.name);
+        if !status.success() {
+            let line = format!("{} exited with {status}", self.name);
+            self.send_error(&mut channel, &line);
+            bail!(line);
+        }
+        Ok(())
+    }
+
+    /// `line` as the last, error line of the output
+    fn send_error(
+        &self,
+        channel: &mut ExtChannel<StyledText>,
+        line: &str
+    ) {
+        let mut text =
+            self.styled_text(line.as_bytes(), Stream::StdErr);
+        text.level = Level::Error;
+        channel.send(text);
+    }
+
+    fn styled_text(&self, line: &[u8], stream: Stream) -> StyledText {
+        let styled_text = parse_byte(line);
+        let (level, tag) = (self.classify)(&styled_text.text, stream);
+        let mut text = StyledText {
+            id: TextSrc::Process {
+                name: self.name.clone(),
+                tag
+            },
+            level,
+            styled_text,
+            hyperlink: vec![],
+            decorations: vec![]
+        };
+        self.linkifier.linkify(&mut text);
+        text
+    }
+}
+
+/// Send the lines of `pipe` to `tx`, as bytes: a line that is not
+/// utf-8 doesn't end the output. Reads until the end of the pipe,
+/// so the child never writes to a closed one, or until a read
+/// fails: such errors, e.g. a broken pipe, don't go away.
+async fn read_lines(
+    pipe: impl AsyncRead + Unpin,
+    stream: Stream,
+    tx: mpsc::Sender<(Vec<u8>, Stream)>
+) -> std::io::Result<()> {
+    let mut reader = BufReader::new(pipe);
+    let mut sending = true;
+    loop {
+        let mut line = vec![];
+        match reader.read_until(b'\n', &mut line).await {
+            Ok(0) => break,
+            Ok(_) => {
+                while matches!(line.last(), Some(b'\n' | b'\r')) {
+                    line.pop();
+                }
+                if sending && tx.send((line, stream)).await.is_err() {
+                    sending = false;
+                }
+            },
+            Err(err) => {
+                error!("read {stream:?} fail: {err:?}");
+                return Err(err);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Lines starting with `error`, `warning`, `note`, `help` or `info`,
+/// in any case, have that level. No tag.
+pub fn classify_by_keyword(
+    line: &str,
+    _stream: Stream
+) -> (Level, Option<String>) {
+    let line = line.trim_start().to_lowercase();
+    let level = if line.starts_with("error") {
+        Level::Error
+    } else if line.starts_with("warn") {
+        Level::Warn
+    } else if line.starts_with("note") {
+        Level::Note
+    } else if line.starts_with("help") {
+        Level::Help
+    } else if line.starts_with("info") {
+        Level::Info
+    } else {
+        Level::None
+    };
+    (level, None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classify_by_keyword() {
+        let level =
+            |line| classify_by_keyword(line, Stream::StdOut).0;
+        assert_eq!(Level::Error, level("error[E0308]: mismatched"));
+        assert_eq!(Level::Error, level("  ERROR: failed"));
+        assert_eq!(Level::Warn, level("warning: unused variable"));
+        assert_eq!(Level::Warn, level("WARN something"));
+        assert_eq!(Level::Note, level("note: required by a bound"));
+        assert_eq!(Level::Help, level("help: remove this"));
+        assert_eq!(Level::Info, level("Info: done"));
+        assert_eq!(Level::None, level("running 3 tests"));
+        assert_eq!(Level::None, level("an error in the middle"));
+        assert_eq!(
+            (Level::Error, None),
+            classify_by_keyword("error", Stream::StdErr)
+        );
+    }
+}
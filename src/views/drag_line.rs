@@ -1,6 +1,7 @@
 use floem::{
     View,
-    event::{Event, EventListener},
+    event::{Event, EventListener, EventPropagation},
+    keyboard::{Key, NamedKey},
     kurbo::Point,
     prelude::{
         Decorators, RwSignal, SignalGet, SignalUpdate,
@@ -9,30 +10,173 @@ use floem::{
     style::CursorStyle
 };
 
-pub fn x_drag_line(left_width: RwSignal<f64>) -> impl View {
+/// The side of a splitter the view it sizes is on, e.g. `Left` for
+/// a tree beside a panel, `Bottom` for a panel docked below.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SplitDirection {
+    Left,
+    Right,
+    Top,
+    Bottom
+}
+
+impl SplitDirection {
+    fn is_vertical(&self) -> bool {
+        matches!(self, SplitDirection::Top | SplitDirection::Bottom)
+    }
+
+    /// the size change of moving the pointer by `delta`
+    fn grow(&self, delta: Point) -> f64 {
+        match self {
+            SplitDirection::Left => delta.x,
+            SplitDirection::Right => -delta.x,
+            SplitDirection::Top => delta.y,
+            SplitDirection::Bottom => -delta.y
+        }
+    }
+}
+
+/// The sizes a splitter allows. Collapsing to 0 is always allowed.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SplitterConstraints {
+    pub min:  f64,
+    pub max:  f64,
+    /// the size change of an arrow key, ten times with shift
+    pub step: f64
+}
+
+impl Default for SplitterConstraints {
+    fn default() -> Self {
+        Self {
+            min:  0.0,
+            max:  f64::INFINITY,
+            step: 10.0
+        }
+    }
+}
+
+impl SplitterConstraints {
+    pub fn new(min: f64, max: f64) -> Self {
+        Self {
+            min,
+            max,
+            ..Default::default()
+        }
+    }
+
+    pub fn clamp(&self, size: f64) -> f64 {
+        size.max(self.min).min(self.max)
+    }
+}
+
+/// A line resizing `size`, the width or height of the view on the
+/// `direction` side of it.
+///
+/// Drag it, or focus it and use the arrow keys. A double click or
+/// Enter collapses the view to 0 and brings it back to the size
+/// before.
+pub fn splitter(
+    direction: SplitDirection,
+    size: RwSignal<f64>,
+    constraints: SplitterConstraints
+) -> impl View {
     let view = empty();
     let view_id = view.id();
     let drag_start: RwSignal<Option<Point>> = create_rw_signal(None);
+    // the size to restore, while collapsed
+    let before_collapse: RwSignal<Option<f64>> =
+        create_rw_signal(None);
+    let resize = move |new_size: f64| {
+        before_collapse.set(None);
+        size.set(constraints.clamp(new_size));
+    };
+    let toggle_collapse =
+        move || match before_collapse.get_untracked() {
+            Some(restored) => resize(restored),
+            None => {
+                before_collapse.set(Some(size.get_untracked()));
+                size.set(0.0);
+            }
+        };
     view.on_event_stop(EventListener::PointerDown, move |event| {
         view_id.request_active();
+        view_id.request_focus();
         if let Event::PointerDown(pointer_event) = event {
             drag_start.set(Some(pointer_event.pos));
         }
     })
     .on_event_stop(EventListener::PointerMove, move |event| {
-        if let Event::PointerMove(pointer_event) = event {
-            if drag_start.get_untracked().is_some()
-                && pointer_event.pos.x != 0.0
-            {
-                left_width.set(
-                    pointer_event.pos.x + left_width.get_untracked()
-                );
+        if let (Event::PointerMove(pointer_event), Some(start)) =
+            (event, drag_start.get_untracked())
+        {
+            let delta = pointer_event.pos - start.to_vec2();
+            let grow = direction.grow(delta);
+            if grow != 0.0 {
+                resize(size.get_untracked() + grow);
             }
         }
     })
     .on_event_stop(EventListener::PointerUp, move |_| {
         drag_start.set(None);
     })
-    .style(|x| x.hover(|x| x.cursor(CursorStyle::ColResize)))
-    .debug_name("drag_line")
+    .on_event_stop(EventListener::DoubleClick, move |_| {
+        toggle_collapse();
+    })
+    .on_event(EventListener::KeyDown, move |event| {
+        let Event::KeyDown(key_event) = event else {
+            return EventPropagation::Continue;
+        };
+        let step = if key_event.modifiers.shift() {
+            constraints.step * 10.0
+        } else {
+            constraints.step
+        };
+        let delta = match &key_event.key.logical_key {
+            Key::Named(NamedKey::ArrowLeft) => Point::new(-step, 0.0),
+            Key::Named(NamedKey::ArrowRight) => Point::new(step, 0.0),
+            Key::Named(NamedKey::ArrowUp) => Point::new(0.0, -step),
+            Key::Named(NamedKey::ArrowDown) => Point::new(0.0, step),
+            Key::Named(NamedKey::Enter) => {
+                toggle_collapse();
+                return EventPropagation::Stop;
+            },
+            // e.g. tab moves the focus on
+            _ => return EventPropagation::Continue
+        };
+        let grow = direction.grow(delta);
+        if grow != 0.0 {
+            resize(size.get_untracked() + grow);
+        }
+        EventPropagation::Stop
+    })
+    .keyboard_navigable()
+    .style(move |x| {
+        let cursor = if direction.is_vertical() {
+            CursorStyle::RowResize
+        } else {
+            CursorStyle::ColResize
+        };
+        x.hover(|x| x.cursor(cursor))
+    })
+    .debug_name("splitter")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clamp() {
+        let constraints = SplitterConstraints::new(60.0, 800.0);
+        assert_eq!(60.0, constraints.clamp(10.0));
+        assert_eq!(60.0, constraints.clamp(-5.0));
+        assert_eq!(500.0, constraints.clamp(500.0));
+        assert_eq!(800.0, constraints.clamp(900.0));
+        assert_eq!(60.0, constraints.clamp(f64::NAN));
+        assert_eq!(10.0, constraints.step);
+
+        let unbounded = SplitterConstraints::default();
+        assert_eq!(0.0, unbounded.clamp(-5.0));
+        assert_eq!(1e9, unbounded.clamp(1e9));
+    }
 }
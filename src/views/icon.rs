@@ -0,0 +1,118 @@
+use crate::views::{
+    svg_from_fn,
+    tree_with_panel::data::{Level, TaskStatus}
+};
+use floem::{
+    action::exec_after,
+    prelude::{SignalGet, SignalUpdate, Svg},
+    reactive::{create_effect, create_rw_signal}
+};
+use std::{rc::Rc, time::Duration};
+
+/// Steps of a turn of `Icon::Spinner`, and the time of a step.
+const SPINNER_STEPS: u32 = 12;
+const SPINNER_STEP: Duration = Duration::from_millis(80);
+
+/// The svgs of `resources/svg`, filled with `currentColor` so they
+/// follow the `color` of their style, but for the yellow warning.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Icon {
+    Empty,
+    Error,
+    Warning,
+    Info,
+    Success,
+    Spinner,
+    ChevronDown,
+    ChevronRight
+}
+
+impl Icon {
+    pub fn svg(&self) -> &'static str {
+        match self {
+            Icon::Empty => {
+                include_str!("../../resources/svg/empty.svg")
+            },
+            Icon::Error => {
+                include_str!("../../resources/svg/error.svg")
+            },
+            Icon::Warning => {
+                include_str!("../../resources/svg/warning.svg")
+            },
+            Icon::Info => {
+                include_str!("../../resources/svg/info.svg")
+            },
+            Icon::Success => {
+                include_str!("../../resources/svg/pass.svg")
+            },
+            Icon::Spinner => {
+                include_str!("../../resources/svg/loading.svg")
+            },
+            Icon::ChevronDown => {
+                include_str!("../../resources/svg/chevron-down.svg")
+            },
+            Icon::ChevronRight => {
+                include_str!("../../resources/svg/chevron-right.svg")
+            }
+        }
+    }
+
+    /// the marker of a node or line of `level`
+    pub fn of_level(level: Level) -> Self {
+        match level {
+            Level::None => Icon::Empty,
+            Level::Success => Icon::Success,
+            Level::Info | Level::Help | Level::Note => Icon::Info,
+            Level::Warn => Icon::Warning,
+            Level::Error => Icon::Error
+        }
+    }
+
+    pub fn of_status(status: TaskStatus) -> Self {
+        match status {
+            TaskStatus::Running => Icon::Spinner,
+            TaskStatus::Succeeded => Icon::Success,
+            TaskStatus::Failed => Icon::Error
+        }
+    }
+
+    /// open or closed chevron of a tree node
+    pub fn of_open(open: bool) -> Self {
+        if open {
+            Icon::ChevronDown
+        } else {
+            Icon::ChevronRight
+        }
+    }
+}
+
+/// An svg view following the icon returned by `icon`. The spinner
+/// turns for as long as it is shown.
+pub fn icon(icon: impl Fn() -> Icon + 'static) -> Svg {
+    let icon = Rc::new(icon);
+    let step = create_rw_signal(0);
+    let turning = icon.clone();
+    create_effect(move |_| {
+        // every step schedules the next one
+        step.get();
+        if turning() == Icon::Spinner {
+            exec_after(SPINNER_STEP, move |_| {
+                step.try_update(|x| *x = (*x + 1) % SPINNER_STEPS);
+            });
+        }
+    });
+    svg_from_fn(move || match icon() {
+        Icon::Spinner => spinner_svg(step.get()),
+        icon => icon.svg().to_string()
+    })
+}
+
+/// `Icon::Spinner` turned by `step`
+fn spinner_svg(step: u32) -> String {
+    let angle = step * 360 / SPINNER_STEPS;
+    Icon::Spinner.svg().replacen(
+        "<path ",
+        &format!("<path transform=\"rotate({angle} 8 8)\" "),
+        1
+    )
+}
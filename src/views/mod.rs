@@ -1,6 +1,7 @@
 use floem::{View, prelude::Svg, reactive::create_effect};
 
 pub mod drag_line;
+pub mod icon;
 pub mod panel;
 pub mod tree_with_panel;
 
@@ -1,40 +1,110 @@
-use crate::views::tree_with_panel::data::{StyledText, VisualLine};
+use crate::views::tree_with_panel::data::{
+    Level, StyledText, VisualLine
+};
+use chrono::{DateTime, Local};
 pub use cursor::*;
-use doc::lines::{line_ending::LineEnding, word::WordCursor};
+use doc::{
+    config::WrapStyle,
+    lines::{
+        buffer::rope_text::{RopeText, RopeTextRef},
+        layout::TextLayout,
+        line_ending::LineEnding,
+        mode::Mode,
+        word::WordCursor
+    }
+};
+pub use export::CopyFormat;
 use floem::{
     Clipboard, ViewId,
+    keyboard::{Key, KeyEvent, NamedKey},
     kurbo::{Point, Rect, Size},
     peniko::Color,
     pointer::{PointerInputEvent, PointerMoveEvent},
-    prelude::{RwSignal, SignalGet, SignalUpdate, SignalWith},
+    prelude::{
+        RwSignal, SignalGet, SignalUpdate, SignalWith, palette
+    },
     reactive::{Scope, batch},
     taffy::NodeId,
-    text::{Attrs, FamilyOwned, LineHeightValue}
+    text::{
+        Attrs, AttrsList, FONT_SYSTEM, FamilyOwned, LineHeightValue,
+        Wrap
+    }
 };
-use floem::prelude::palette;
+pub use grouping::*;
+pub use height_index::*;
+use layout_cache::LayoutCache;
 pub use lines::*;
-use log::{error, info};
+pub use linkify::*;
+use log::{error, warn};
+use std::{cell::RefCell, ops::Range, rc::Rc};
 
 mod cursor;
+mod export;
+mod grouping;
+mod height_index;
+mod layout_cache;
 mod lines;
+mod linkify;
 
 #[derive(Clone, Copy)]
 pub struct DocManager {
-    pub panel_id:   ViewId,
-    pub inner_node: Option<NodeId>,
-    doc:            RwSignal<SimpleDoc>
+    pub panel_id:    ViewId,
+    pub inner_node:  Option<NodeId>,
+    pub follow_tail: RwSignal<FollowTail>,
+    /// the lines shown, see `SimpleDoc::update_display`
+    pub display:     RwSignal<DisplayId>,
+    /// bumped by `SimpleDoc::clear`, for the views built on the
+    /// output, e.g. the tree
+    pub cleared:     RwSignal<usize>,
+    doc:             RwSignal<SimpleDoc>
+}
+
+/// The tooltip of the hovered hyperlink.
+#[derive(Clone, Debug, PartialEq)]
+pub struct HoverTip {
+    /// the pointer when the link was entered, relative to the
+    /// viewport
+    pub position: Point,
+    /// see `Hyperlink::target`
+    pub target:   String,
+    /// source lines around the target, while ctrl is held
+    pub preview:  Option<String>
+}
+
+/// Whether the panel keeps the latest output in view, like `tail -f`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FollowTail {
+    Following,
+    /// `new_lines` arrived since. Scrolling down to the bottom
+    /// follows again, unless `pinned`: paused from the menu or by a
+    /// jump to a diagnostic.
+    Paused {
+        new_lines: usize,
+        pinned:    bool
+    }
 }
 
 impl DocManager {
     #[allow(clippy::too_many_arguments)]
     pub fn new(cx: Scope, id: ViewId, doc_style: DocStyle) -> Self {
         let hover_hyperlink = cx.create_rw_signal(None);
+        let hover_tip = cx.create_rw_signal(None);
+        let follow_tail = cx.create_rw_signal(FollowTail::Following);
+        let display = cx.create_rw_signal(DisplayId::All);
+        let cleared = cx.create_rw_signal(0);
         Self {
-            panel_id:   id,
+            panel_id: id,
             inner_node: None,
-            doc:        cx.create_rw_signal(SimpleDoc::new(
+            follow_tail,
+            display,
+            cleared,
+            doc: cx.create_rw_signal(SimpleDoc::new(
                 id,
                 hover_hyperlink,
+                hover_tip,
+                follow_tail,
+                display,
+                cleared,
                 doc_style
             ))
         }
@@ -47,6 +117,10 @@ impl DocManager {
         self.doc.with_untracked(f)
     }
 
+    pub fn with<O>(&self, f: impl FnOnce(&SimpleDoc) -> O) -> O {
+        self.doc.with(f)
+    }
+
     pub fn get(&self) -> SimpleDoc {
         self.doc.get()
     }
@@ -69,30 +143,210 @@ impl DocManager {
 
 #[derive(Clone, Debug)]
 pub struct DocStyle {
-    pub font_family:  String,
-    pub font_size:    f32,
-    pub line_height:  f64,
-    pub selection_bg: Color,
-    pub fg_color:     Color
+    pub font_family: String,
+    pub font_size:   f32,
+    pub line_height: f64,
+    pub wrap_style:  WrapStyle,
+    /// used by `WrapStyle::WrapWidth`
+    pub wrap_width:  f64,
+    pub gutter:      GutterStyle,
+    pub theme:       PanelTheme
+}
+
+/// Colors of the panel. Text without ansi color uses `foreground`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PanelTheme {
+    pub background:      Color,
+    pub foreground:      Color,
+    pub selection_bg:    Color,
+    pub caret:           Color,
+    pub hyperlink:       Color,
+    pub hyperlink_hover: Color,
+    /// background of the lines of a warning
+    pub warn_tint:       Color,
+    /// background of the lines of an error
+    pub error_tint:      Color,
+    /// background of the lines of an info, help or note
+    pub note_tint:       Color,
+    /// background of the lines of a passed test
+    pub success_tint:    Color,
+    pub gutter_bg:       Color,
+    pub gutter_fg:       Color
+}
+
+impl PanelTheme {
+    pub fn light() -> Self {
+        Self {
+            background:      Color::WHITE,
+            foreground:      Color::BLACK,
+            selection_bg:    palette::css::BLUE_VIOLET
+                .multiply_alpha(0.3),
+            caret:           Color::BLACK,
+            hyperlink:       Color::BLACK,
+            hyperlink_hover: palette::css::ROYAL_BLUE,
+            warn_tint:       Color::from_rgba8(255, 204, 102, 40),
+            error_tint:      Color::from_rgba8(255, 153, 153, 40),
+            note_tint:       Color::from_rgba8(117, 190, 255, 30),
+            success_tint:    Color::from_rgba8(115, 201, 145, 30),
+            gutter_bg:       Color::from_rgb8(245, 245, 245),
+            gutter_fg:       Color::from_rgb8(128, 128, 128)
+        }
+    }
+
+    pub fn dark() -> Self {
+        Self {
+            background:      Color::from_rgb8(30, 31, 34),
+            foreground:      Color::from_rgb8(204, 204, 204),
+            selection_bg:    Color::from_rgba8(33, 66, 131, 200),
+            caret:           Color::from_rgb8(206, 208, 214),
+            hyperlink:       Color::from_rgb8(204, 204, 204),
+            hyperlink_hover: Color::from_rgb8(86, 156, 214),
+            warn_tint:       Color::from_rgba8(255, 204, 102, 30),
+            error_tint:      Color::from_rgba8(255, 153, 153, 30),
+            note_tint:       Color::from_rgba8(117, 190, 255, 20),
+            success_tint:    Color::from_rgba8(115, 201, 145, 20),
+            gutter_bg:       Color::from_rgb8(37, 38, 41),
+            gutter_fg:       Color::from_rgb8(110, 112, 118)
+        }
+    }
+
+    /// `None` for lines without a level
+    pub fn level_tint(&self, level: Level) -> Option<Color> {
+        match level {
+            Level::None => None,
+            Level::Success => Some(self.success_tint),
+            Level::Info | Level::Help | Level::Note => {
+                Some(self.note_tint)
+            },
+            Level::Warn => Some(self.warn_tint),
+            Level::Error => Some(self.error_tint)
+        }
+    }
+
+    /// the tint without transparency, for markers
+    pub fn level_color(&self, level: Level) -> Option<Color> {
+        self.level_tint(level).map(|x| x.with_alpha(1.0))
+    }
+}
+
+impl Default for PanelTheme {
+    fn default() -> Self {
+        Self::light()
+    }
+}
+
+/// Optional columns of the gutter, left of the fold chevrons.
+#[derive(Clone, Debug, Default)]
+pub struct GutterStyle {
+    pub line_number: bool,
+    pub timestamp:   Option<Timestamp>,
+    /// error/warning marker on the first line of a `StyledText`
+    pub level:       bool
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Timestamp {
+    /// since the first output, `+mm:ss.mmm`
+    Relative,
+    /// local time, `hh:mm:ss.mmm`
+    Absolute
+}
+
+impl Timestamp {
+    pub fn format(
+        &self,
+        time: DateTime<Local>,
+        started: DateTime<Local>
+    ) -> String {
+        match self {
+            Timestamp::Relative => {
+                let millis = (time - started).num_milliseconds();
+                format!(
+                    "+{:02}:{:02}.{:03}",
+                    millis / 60_000,
+                    millis / 1000 % 60,
+                    millis % 1000
+                )
+            },
+            Timestamp::Absolute => {
+                time.format("%H:%M:%S%.3f").to_string()
+            },
+        }
+    }
+
+    /// chars of a formatted timestamp
+    fn chars(&self) -> usize {
+        match self {
+            Timestamp::Relative => 10,
+            Timestamp::Absolute => 12
+        }
+    }
+}
+
+/// x ranges of the gutter columns, `None` if disabled. The gutter
+/// ends where `fold` ends.
+#[derive(Clone, Debug)]
+pub struct GutterLayout {
+    pub line_number: Option<Range<f64>>,
+    pub timestamp:   Option<Range<f64>>,
+    pub level:       Option<Range<f64>>,
+    pub fold:        Range<f64>
+}
+
+impl GutterLayout {
+    pub fn width(&self) -> f64 {
+        self.fold.end
+    }
 }
 
 impl DocStyle {
     pub fn attrs<'a>(&self, family: &'a [FamilyOwned]) -> Attrs<'a> {
+        self.scaled_attrs(family, 1.0)
+    }
+
+    /// the width of "0", monospace fonts assumed
+    pub fn char_width(&self) -> f64 {
+        let family: Vec<FamilyOwned> =
+            FamilyOwned::parse_list(&self.font_family).collect();
+        let mut font_system = FONT_SYSTEM.lock();
+        TextLayout::new_with_config(
+            0,
+            "0",
+            AttrsList::new(self.attrs(&family)),
+            &mut font_system,
+            None,
+            Wrap::None
+        )
+        .size()
+        .width
+    }
+
+    /// attrs with font size and line height scaled, for headers
+    pub fn scaled_attrs<'a>(
+        &self,
+        family: &'a [FamilyOwned],
+        scale: f32
+    ) -> Attrs<'a> {
         Attrs::new()
             .family(family)
-            .font_size(self.font_size)
-            .line_height(LineHeightValue::Px(self.line_height as f32))
+            .font_size(self.font_size * scale)
+            .color(self.theme.foreground)
+            .line_height(LineHeightValue::Px(
+                self.line_height as f32 * scale
+            ))
     }
 }
 
 impl Default for DocStyle {
     fn default() -> Self {
         Self {
-            font_family:  "JetBrains Mono".to_string(),
-            font_size:    13.0,
-            line_height:  23.0,
-            selection_bg: palette::css::BLUE_VIOLET,
-            fg_color:     Color::BLACK
+            font_family: "JetBrains Mono".to_string(),
+            font_size:   13.0,
+            line_height: 23.0,
+            wrap_style:  WrapStyle::None,
+            wrap_width:  800.0,
+            gutter:      GutterStyle::default(),
+            theme:       PanelTheme::default()
         }
     }
 }
@@ -105,18 +359,41 @@ pub struct SimpleDoc {
     pub viewport:        Rect,
     pub cursor:          Cursor,
     pub hover_hyperlink: RwSignal<Option<usize>>,
+    pub hover_tip:       RwSignal<Option<HoverTip>>,
     pub style:           DocStyle,
-    pub auto_scroll:     bool,
-    pub lines:           Lines
+    pub follow_tail:     RwSignal<FollowTail>,
+    pub display:         RwSignal<DisplayId>,
+    pub cleared:         RwSignal<usize>,
+    pub lines:           Lines,
+    /// width of a digit, to size the gutter columns
+    char_width:          f64,
+    /// line numbers and timestamps, shaped once
+    gutter_layouts:      RefCell<LayoutCache<Rc<TextLayout>, String>>,
+    /// the last pointer position, for the context menu
+    pointer:             Point,
+    /// whether ctrl was held at the last pointer move or key event
+    hover_ctrl:          bool,
+    /// the files previewed by the hover tip, read once until the
+    /// output is cleared. `None` when unreadable.
+    sources: RefCell<LayoutCache<Option<Rc<str>>, String>>,
+    /// a restored scroll position, waiting for the lines to be that
+    /// high. See `scroll_to_y_when_ready`.
+    pending_scroll_y:    Option<f64>
 }
 
 impl SimpleDoc {
     pub fn new(
         id: ViewId,
         hover_hyperlink: RwSignal<Option<usize>>,
+        hover_tip: RwSignal<Option<HoverTip>>,
+        follow_tail: RwSignal<FollowTail>,
+        display: RwSignal<DisplayId>,
+        cleared: RwSignal<usize>,
         style: DocStyle
     ) -> Self {
-        Self {
+        let lines = Lines::new(style.clone());
+        let char_width = style.char_width();
+        let mut doc = Self {
             id,
             // visual_line: vec![],
             line_ending: LineEnding::Lf,
@@ -126,10 +403,21 @@ impl SimpleDoc {
                 position: Position::None
             },
             hover_hyperlink,
+            hover_tip,
             style,
-            auto_scroll: true,
-            lines: Default::default()
-        }
+            follow_tail,
+            display,
+            cleared,
+            lines,
+            char_width,
+            gutter_layouts: RefCell::new(LayoutCache::new(256)),
+            pointer: Point::ZERO,
+            hover_ctrl: false,
+            sources: RefCell::new(LayoutCache::new(32)),
+            pending_scroll_y: None
+        };
+        doc.lines.set_wrap_width(doc.wrap_width());
+        doc
     }
 
     /// return (offset_of_buffer, line)
@@ -137,17 +425,18 @@ impl SimpleDoc {
         &self,
         point: Point
     ) -> anyhow::Result<(usize, usize)> {
+        let point = self.text_point(point);
         let last_line = self.lines.lines_len()?;
-        let line = (point.y / self.style.line_height) as usize;
-        if line >= last_line {
+        let Some(line) = self.lines.line_of_y(point.y)? else {
             return Ok((
-                self.lines.line_info()?.0.len().max(1) - 1,
+                self.lines.line_info()?.rope.len().max(1) - 1,
                 last_line.max(1) - 1
             ));
-        }
+        };
         let text = self.lines.text_layout_of_line(line)?;
-
-        let hit_point = text.hit_point(Point::new(point.x, 0.0));
+        let text_top = self.lines.text_y_of_line(line)?;
+        let hit_point =
+            text.hit_point(Point::new(point.x, point.y - text_top));
         let offset = self.offset_of_line(line)? + hit_point.index;
         // debug!(
         //     "offset_of_pos point={point:?} line={line} index={}
@@ -162,31 +451,41 @@ impl SimpleDoc {
         &mut self,
         event: PointerInputEvent
     ) -> anyhow::Result<()> {
+        self.pointer = event.pos;
+        if event.button.is_secondary() {
+            return Ok(());
+        }
         match event.count {
             1 => {
+                if let Some(x) = self.gutter_x(event.pos) {
+                    if !self.gutter_layout().fold.contains(&x) {
+                        return Ok(());
+                    }
+                    if let Some(line) =
+                        self.lines.line_of_y(event.pos.y)?
+                    {
+                        self.toggle_fold(line)?;
+                    }
+                    return Ok(());
+                }
                 if self.hover_hyperlink.get_untracked().is_some() {
+                    let point = self.text_point(event.pos);
                     if let Some(link) =
-                        self.lines.hyperlink_by_point(event.pos)?
+                        self.lines.hyperlink_by_point(point)?
                     {
-                        info!("todo {:?}", link);
+                        self.open_hyperlink(link);
+                        return Ok(());
                     }
                 }
                 let offset = self.offset_of_pos(event.pos)?.0;
                 self.cursor.dragging = true;
-                if event.modifiers.shift() {
-                    self.cursor.position = Position::Region {
-                        start: self.cursor.start().unwrap_or(offset),
-                        end:   offset
-                    };
-                } else {
-                    self.cursor.position = Position::Caret(offset);
-                }
+                self.cursor.move_to(offset, event.modifiers.shift());
                 self.id.request_paint();
             },
             2 => {
                 let offset = self.offset_of_pos(event.pos)?.0;
                 let (start_code, end_code) = WordCursor::new(
-                    &self.lines.line_info()?.0,
+                    &self.lines.line_info()?.rope,
                     offset
                 )
                 .select_word();
@@ -224,24 +523,99 @@ impl SimpleDoc {
         &mut self,
         event: PointerMoveEvent
     ) -> anyhow::Result<()> {
-        if let Some(x) = self.lines.in_hyperlink_region(event.pos)? {
-            if self.hover_hyperlink.get_untracked().is_none() {
-                self.hover_hyperlink.set(Some(x));
+        self.pointer = event.pos;
+        let hover = match self.gutter_x(event.pos) {
+            // the text scrolled below the gutter
+            Some(_) => None,
+            None => {
+                let point = self.text_point(event.pos);
+                self.lines.in_hyperlink_region(point)?
             }
-        } else if self.hover_hyperlink.get_untracked().is_some() {
-            self.hover_hyperlink.set(None);
+        };
+        let ctrl = event.modifiers.control();
+        if self.hover_hyperlink.get_untracked() != hover
+            || self.hover_ctrl != ctrl
+        {
+            self.hover_hyperlink.set(hover);
+            self.hover_ctrl = ctrl;
+            self.update_hover_tip(hover, ctrl, event.pos);
+            self.id.request_paint();
         }
         if self.cursor.dragging {
             let offset = self.offset_of_pos(event.pos)?.0;
-            self.cursor.position = Position::Region {
-                start: self.cursor.start().unwrap_or(offset),
-                end:   offset
-            };
+            self.cursor.move_to(offset, true);
             self.id.request_paint();
         }
         Ok(())
     }
 
+    pub fn pointer_leave(&mut self) {
+        if self.hover_hyperlink.get_untracked().is_some() {
+            self.hover_hyperlink.set(None);
+            self.hover_tip.set(None);
+            self.id.request_paint();
+        }
+    }
+
+    fn update_hover_tip(
+        &self,
+        hover: Option<usize>,
+        ctrl: bool,
+        pos: Point
+    ) {
+        let tip = hover
+            .and_then(|index| self.lines.hyperlinks.get(index))
+            .map(|link| HoverTip {
+                position: pos - self.viewport.origin().to_vec2(),
+                target:   link.target(),
+                preview:  if ctrl {
+                    self.preview(link)
+                } else {
+                    None
+                }
+            });
+        self.hover_tip.set(tip);
+    }
+
+    /// See `Hyperlink::preview`, with the file read once.
+    fn preview(&self, link: &Hyperlink) -> Option<String> {
+        let src = link.src()?;
+        let key = src.to_string();
+        let cached = self.sources.borrow_mut().get(&key);
+        let content = match cached {
+            Some(content) => content,
+            None => {
+                let content = std::fs::read_to_string(src)
+                    .map_err(|err| warn!("read {src} fail: {err:?}"))
+                    .ok()
+                    .map(Rc::from);
+                self.sources
+                    .borrow_mut()
+                    .insert(key, content.clone());
+                content
+            }
+        };
+        link.preview(&content?, 3)
+    }
+
+    /// Follow ctrl on key events, to show or hide the preview of the
+    /// hovered link without moving the pointer.
+    pub fn key_modifiers(&mut self, event: &KeyEvent, down: bool) {
+        let ctrl = match &event.key.logical_key {
+            Key::Named(NamedKey::Control) => down,
+            _ => event.modifiers.control()
+        };
+        if self.hover_ctrl == ctrl {
+            return;
+        }
+        self.hover_ctrl = ctrl;
+        let hover = self.hover_hyperlink.get_untracked();
+        if hover.is_some() {
+            self.update_hover_tip(hover, ctrl, self.pointer);
+            self.id.request_paint();
+        }
+    }
+
     pub fn pointer_up(
         &mut self,
         _event: PointerInputEvent
@@ -250,17 +624,386 @@ impl SimpleDoc {
         Ok(())
     }
 
+    /// Handle caret movement and selection keys. Return `true` if the
+    /// key was consumed.
+    pub fn key_down(
+        &mut self,
+        event: &KeyEvent
+    ) -> anyhow::Result<bool> {
+        if self.lines.line_info()?.rope.is_empty() {
+            return Ok(false);
+        }
+        let extend = event.modifiers.shift();
+        let control = event.modifiers.control();
+        let current = self.cursor.offset().unwrap_or_default();
+        let offset = match &event.key.logical_key {
+            Key::Character(c)
+                if control && c.eq_ignore_ascii_case("a") =>
+            {
+                self.select_all()?;
+                return Ok(true);
+            },
+            Key::Character(c)
+                if control
+                    && extend
+                    && c.eq_ignore_ascii_case("c") =>
+            {
+                self.copy_diagnostic(CopyFormat::PlainText)?;
+                return Ok(true);
+            },
+            Key::Named(NamedKey::ArrowLeft) => {
+                match self.cursor.region() {
+                    Some((start, _)) if !extend => start,
+                    _ => self.offset_of_left(current, control)?
+                }
+            },
+            Key::Named(NamedKey::ArrowRight) => {
+                match self.cursor.region() {
+                    Some((_, end)) if !extend => end,
+                    _ => self.offset_of_right(current, control)?
+                }
+            },
+            Key::Named(NamedKey::ArrowUp) => {
+                self.offset_of_adjacent_row(current, false)?
+            },
+            Key::Named(NamedKey::ArrowDown) => {
+                self.offset_of_adjacent_row(current, true)?
+            },
+            Key::Named(NamedKey::PageUp) => self.offset_of_vertical(
+                current,
+                -self.viewport.height()
+            )?,
+            Key::Named(NamedKey::PageDown) => self
+                .offset_of_vertical(
+                    current,
+                    self.viewport.height()
+                )?,
+            Key::Named(NamedKey::Home) => {
+                if control {
+                    0
+                } else {
+                    self.offset_of_line(
+                        self.line_of_offset(current)?
+                    )?
+                }
+            },
+            Key::Named(NamedKey::F8) => {
+                let level = if event.modifiers.alt() {
+                    Level::Warn
+                } else {
+                    Level::Error
+                };
+                self.goto_diagnostic(level, !extend)?;
+                return Ok(true);
+            },
+            Key::Named(NamedKey::End) => {
+                if control {
+                    self.last_offset()?
+                } else {
+                    self.line_end_offset(
+                        self.line_of_offset(current)?
+                    )?
+                }
+            },
+            _ => return Ok(false)
+        };
+        self.cursor.move_to(offset, extend);
+        self.scroll_to_cursor()?;
+        self.id.request_paint();
+        Ok(true)
+    }
+
+    pub fn select_all(&mut self) -> anyhow::Result<()> {
+        let len = self.lines.line_info()?.rope.len();
+        self.cursor.position = Position::Region {
+            start: 0,
+            end:   len
+        };
+        self.id.request_paint();
+        Ok(())
+    }
+
+    pub fn next_error(&mut self) -> anyhow::Result<()> {
+        self.goto_diagnostic(Level::Error, true)
+    }
+
+    pub fn prev_error(&mut self) -> anyhow::Result<()> {
+        self.goto_diagnostic(Level::Error, false)
+    }
+
+    pub fn next_warning(&mut self) -> anyhow::Result<()> {
+        self.goto_diagnostic(Level::Warn, true)
+    }
+
+    pub fn prev_warning(&mut self) -> anyhow::Result<()> {
+        self.goto_diagnostic(Level::Warn, false)
+    }
+
+    /// Move the caret to the header of the next/previous diagnostic
+    /// with `level`, like F8 in IDEs.
+    pub fn goto_diagnostic(
+        &mut self,
+        level: Level,
+        forward: bool
+    ) -> anyhow::Result<()> {
+        let line = match self.cursor.offset() {
+            Some(offset) => Some(self.line_of_offset(offset)?),
+            None => None
+        };
+        let Some(line) =
+            self.lines.diagnostic_line(line, level, forward)?
+        else {
+            return Ok(());
+        };
+        self.pause_follow_tail(true);
+        self.cursor.position =
+            Position::Caret(self.offset_of_line(line)?);
+        self.scroll_to_cursor()?;
+        self.id.request_paint();
+        Ok(())
+    }
+
+    fn offset_of_left(
+        &self,
+        offset: usize,
+        word: bool
+    ) -> anyhow::Result<usize> {
+        let rope = &self.lines.line_info()?.rope;
+        Ok(if word {
+            WordCursor::new(rope, offset)
+                .prev_boundary(Mode::Insert)
+                .unwrap_or(0)
+        } else {
+            RopeTextRef::new(rope).prev_grapheme_offset(offset, 1, 0)
+        })
+    }
+
+    fn offset_of_right(
+        &self,
+        offset: usize,
+        word: bool
+    ) -> anyhow::Result<usize> {
+        let rope = &self.lines.line_info()?.rope;
+        let last_offset = self.last_offset()?;
+        Ok(if word {
+            WordCursor::new(rope, offset)
+                .next_boundary()
+                .unwrap_or(last_offset)
+                .min(last_offset)
+        } else {
+            RopeTextRef::new(rope).next_grapheme_offset(
+                offset,
+                1,
+                last_offset
+            )
+        })
+    }
+
+    /// the offset `delta_y` above/below `offset`, keeping the column
+    fn offset_of_vertical(
+        &self,
+        offset: usize,
+        delta_y: f64
+    ) -> anyhow::Result<usize> {
+        let Some((point, line, _)) = self.point_of_offset(offset)?
+        else {
+            return Ok(offset);
+        };
+        let row_height = self.lines.row_height_of_line(line)?;
+        let y = (point.y + row_height / 2.0 + delta_y).max(0.0);
+        Ok(self.offset_of_pos(Point::new(point.x, y))?.0)
+    }
+
+    /// the offset one visual row above/below `offset`, keeping the
+    /// column
+    fn offset_of_adjacent_row(
+        &self,
+        offset: usize,
+        down: bool
+    ) -> anyhow::Result<usize> {
+        let Some((point, ..)) = self.point_of_offset(offset)? else {
+            return Ok(offset);
+        };
+        let Some(y) = self.lines.y_of_adjacent_row(offset, down)?
+        else {
+            return Ok(offset);
+        };
+        Ok(self.offset_of_pos(Point::new(point.x, y))?.0)
+    }
+
+    fn line_end_offset(&self, line: usize) -> anyhow::Result<usize> {
+        let start = self.offset_of_line(line)?;
+        let next_start = self.offset_of_line(line + 1)?;
+        Ok(next_start
+            .saturating_sub(self.line_ending.len())
+            .max(start))
+    }
+
+    fn last_offset(&self) -> anyhow::Result<usize> {
+        Ok(self.lines.line_info()?.rope.len().max(1) - 1)
+    }
+
+    fn scroll_to_cursor(&self) -> anyhow::Result<()> {
+        if let Some(rect) = self.position_of_cursor()? {
+            self.id.scroll_to(Some(rect));
+        }
+        Ok(())
+    }
+
     pub fn copy_select(&self) -> anyhow::Result<()> {
+        self.copy_select_as(CopyFormat::PlainText)
+    }
+
+    pub fn copy_select_as(
+        &self,
+        format: CopyFormat
+    ) -> anyhow::Result<()> {
         if let Some((start, end)) = self.cursor.region() {
-            let content = self
-                .lines
-                .line_info()?
-                .0
-                .slice_to_cow(start..end)
-                .to_string();
-            if let Err(err) = Clipboard::set_contents(content) {
+            self.copy_range(start..end, format)?;
+        }
+        Ok(())
+    }
+
+    /// Copy the whole `StyledText` around the caret, e.g. a complete
+    /// rustc diagnostic, whatever is selected.
+    pub fn copy_diagnostic(
+        &self,
+        format: CopyFormat
+    ) -> anyhow::Result<()> {
+        let Some(offset) = self.cursor.offset() else {
+            return Ok(());
+        };
+        let info = self.lines.line_info()?;
+        let line = info.rope.line_of_offset(offset);
+        let Some(index) = info.block_of_line(line) else {
+            return Ok(());
+        };
+        let block = &info.blocks[index];
+        let start = self.offset_of_line(block.start_line)?;
+        let end = self.line_end_offset(block.end_line - 1)?;
+        self.copy_range(start..end, format)
+    }
+
+    /// Drop all output shown in the panel. The tree of a
+    /// `TreePanelData` follows by `cleared`.
+    pub fn clear(&mut self) {
+        self.lines.clear();
+        self.sources.get_mut().clear();
+        self.pending_scroll_y = None;
+        self.cleared.update(|x| *x += 1);
+        self.cursor.position = Position::None;
+        self.hover_hyperlink.set(None);
+        self.hover_tip.set(None);
+        self.id.request_layout();
+        self.id.request_paint();
+    }
+
+    /// Follow new output or not, jumping to the bottom when turned
+    /// on.
+    pub fn set_follow_tail(&mut self, follow: bool) {
+        if follow {
+            self.pending_scroll_y = None;
+            self.follow_tail.set(FollowTail::Following);
+            if let Err(err) = self.auto_scroll(false) {
                 error!("{err:?}");
             }
+        } else {
+            self.pause_follow_tail(true);
+        }
+    }
+
+    pub fn is_following(&self) -> bool {
+        self.follow_tail.get_untracked() == FollowTail::Following
+    }
+
+    /// `pinned`: only `set_follow_tail` follows again
+    fn pause_follow_tail(&self, pinned: bool) {
+        let new_lines = match self.follow_tail.get_untracked() {
+            FollowTail::Following => 0,
+            // pinning a pause of a scroll
+            FollowTail::Paused {
+                new_lines,
+                pinned: false
+            } if pinned => new_lines,
+            FollowTail::Paused { .. } => return
+        };
+        self.follow_tail
+            .set(FollowTail::Paused { new_lines, pinned });
+    }
+
+    /// the last line is in viewport
+    fn at_bottom(&self) -> anyhow::Result<bool> {
+        Ok(self.viewport.y1 >= self.lines.line_info()?.height() - 1.0)
+    }
+
+    pub fn hyperlink_at_pointer(
+        &self
+    ) -> anyhow::Result<Option<Hyperlink>> {
+        Ok(self
+            .lines
+            .hyperlink_by_point(self.text_point(self.pointer))?
+            .cloned())
+    }
+
+    /// the source of the output under the pointer
+    pub fn text_src_at_pointer(
+        &self
+    ) -> anyhow::Result<Option<&TextSrc>> {
+        let Some(line) = self.lines.line_of_y(self.pointer.y)? else {
+            return Ok(None);
+        };
+        let info = self.lines.line_info()?;
+        Ok(info
+            .block_of_line(line)
+            .map(|index| &info.blocks[index].text_src))
+    }
+
+    /// The node of the crate of the output under the pointer, if the
+    /// grouping has one.
+    pub fn crate_display_at_pointer(
+        &self
+    ) -> anyhow::Result<Option<DisplayId>> {
+        let Some(line) = self.lines.line_of_y(self.pointer.y)? else {
+            return Ok(None);
+        };
+        let info = self.lines.line_info()?;
+        let Some(block) =
+            info.block_of_line(line).map(|index| &info.blocks[index])
+        else {
+            return Ok(None);
+        };
+        let path = self.lines.path(&block.text_src, block.level);
+        let Some(end) =
+            path.iter().position(|x| matches!(x, Segment::Crate(_)))
+        else {
+            return Ok(None);
+        };
+        let id = DisplayId::Path(path[..=end].to_vec());
+        Ok(Some(match &block.task {
+            Some(task) => id.in_task(task),
+            None => id
+        }))
+    }
+
+    /// Open the file or url of `link` with the default application.
+    pub fn open_hyperlink(&self, link: &Hyperlink) {
+        let target = match link {
+            Hyperlink::File { src, .. } => src.as_str(),
+            Hyperlink::Url { url, .. } => url.as_str()
+        };
+        if let Err(err) = open_with_system(target) {
+            error!("open {target} fail: {err:?}");
+        }
+    }
+
+    fn copy_range(
+        &self,
+        range: Range<usize>,
+        format: CopyFormat
+    ) -> anyhow::Result<()> {
+        let content = self.lines.export(range, format)?;
+        if let Err(err) = Clipboard::set_contents(content) {
+            error!("{err:?}");
         }
         Ok(())
     }
@@ -269,17 +1012,17 @@ impl SimpleDoc {
         let Some(offset) = self.cursor.offset() else {
             return Ok(None);
         };
-        let Some((point, _line, _)) = self.point_of_offset(offset)?
+        let Some((point, line, _)) = self.point_of_offset(offset)?
         else {
             return Ok(None);
         };
         // debug!(
         //     "position_of_cursor offset={offset}, point={point:?}, \
-        //      line={_line}"
+        //      line={line}"
         // );
         let rect = Rect::from_origin_size(
             (point.x - 1.0, point.y),
-            (2.0, self.style.line_height)
+            (2.0, self.lines.row_height_of_line(line)?)
         );
         Ok(Some(rect))
     }
@@ -288,15 +1031,138 @@ impl SimpleDoc {
         &self,
         offset: usize
     ) -> anyhow::Result<Option<(Point, usize, usize)>> {
-        let rs = self.lines.point_of_offset(offset)?;
-        Ok(rs.map(|(mut point, line, offset)| {
-            point.y = self.height_of_line(line);
-            (point, line, offset)
-        }))
+        let Some((mut point, line, offset)) =
+            self.lines.point_of_offset(offset)?
+        else {
+            return Ok(None);
+        };
+        point.x += self.gutter_width();
+        point.y += self.height_of_line(line)?;
+        Ok(Some((point, line, offset)))
+    }
+
+    fn height_of_line(&self, line: usize) -> anyhow::Result<f64> {
+        self.lines.y_of_line(line)
+    }
+
+    /// Width of the left gutter: the optional columns and the fold
+    /// chevrons. Text starts right after it.
+    pub fn gutter_width(&self) -> f64 {
+        self.gutter_layout().width()
+    }
+
+    pub fn gutter_layout(&self) -> GutterLayout {
+        let gutter = &self.style.gutter;
+        let padding = self.char_width;
+        let mut x = 0.0;
+        let mut column = |enabled: bool, width: f64| {
+            enabled.then(|| {
+                let start = x;
+                x += width + padding;
+                start..x - padding
+            })
+        };
+        let digits = self
+            .lines
+            .lines_len()
+            .unwrap_or_default()
+            .to_string()
+            .len()
+            .max(3);
+        let line_number = column(
+            gutter.line_number,
+            digits as f64 * self.char_width
+        );
+        let timestamp = gutter.timestamp.and_then(|timestamp| {
+            column(true, timestamp.chars() as f64 * self.char_width)
+        });
+        let level = column(gutter.level, self.style.line_height);
+        GutterLayout {
+            line_number,
+            timestamp,
+            level,
+            fold: x..x + self.style.line_height
+        }
+    }
+
+    /// The x of `point` in the gutter, if on it. The gutter stays at
+    /// the left of the viewport when the text scrolls horizontally.
+    pub fn gutter_x(&self, point: Point) -> Option<f64> {
+        let x = point.x - self.viewport.x0;
+        (x < self.gutter_width()).then_some(x)
+    }
+
+    /// The shaped `text` of a gutter column, e.g. a line number.
+    pub fn gutter_text_layout(&self, text: &str) -> Rc<TextLayout> {
+        let key = text.to_string();
+        let cached = self.gutter_layouts.borrow_mut().get(&key);
+        if let Some(layout) = cached {
+            return layout;
+        }
+        let family: Vec<FamilyOwned> =
+            FamilyOwned::parse_list(&self.style.font_family)
+                .collect();
+        let attrs = self
+            .style
+            .attrs(&family)
+            .color(self.style.theme.gutter_fg);
+        let layout = {
+            let mut font_system = FONT_SYSTEM.lock();
+            Rc::new(TextLayout::new_with_config(
+                0,
+                text,
+                AttrsList::new(attrs),
+                &mut font_system,
+                None,
+                Wrap::None
+            ))
+        };
+        self.gutter_layouts.borrow_mut().insert(key, layout.clone());
+        layout
+    }
+
+    /// convert a point of the view to a point relative to the text
+    fn text_point(&self, point: Point) -> Point {
+        Point::new(point.x - self.gutter_width(), point.y)
+    }
+
+    /// Fold/unfold the diagnostic whose header is `line`
+    pub fn toggle_fold(&mut self, line: usize) -> anyhow::Result<()> {
+        if self.lines.line_info_mut()?.toggle_fold(line) {
+            self.caret_out_of_folded()?;
+            self.measure_viewport();
+            self.id.request_layout();
+            self.id.request_paint();
+        }
+        Ok(())
     }
 
-    fn height_of_line(&self, line: usize) -> f64 {
-        line as f64 * self.style.line_height
+    pub fn fold_all(&mut self, folded: bool) -> anyhow::Result<()> {
+        self.lines.line_info_mut()?.fold_all(folded);
+        self.caret_out_of_folded()?;
+        self.measure_viewport();
+        self.id.request_layout();
+        self.id.request_paint();
+        Ok(())
+    }
+
+    /// move a caret hidden by a folded block to the block header
+    fn caret_out_of_folded(&mut self) -> anyhow::Result<()> {
+        let Some(offset) = self.cursor.offset() else {
+            return Ok(());
+        };
+        let info = self.lines.line_info()?;
+        let line = info.rope.line_of_offset(offset);
+        if line < info.lines.len() && !info.is_visible(line) {
+            let header = info
+                .block_of_line(line)
+                .map(|index| info.blocks[index].start_line);
+            if let Some(header) = header {
+                self.cursor.position =
+                    Position::Caret(self.offset_of_line(header)?);
+            }
+        }
+        Ok(())
     }
 
     pub fn select_of_cursor(&self) -> anyhow::Result<Vec<Rect>> {
@@ -304,41 +1170,54 @@ impl SimpleDoc {
         else {
             return Ok(vec![]);
         };
-        let Some((start_point, mut start_line, _)) =
+        let Some((start_point, start_line, _)) =
             self.point_of_offset(start_offset)?
         else {
             return Ok(vec![]);
         };
-        let Some((mut end_point, end_line, _)) =
+        let Some((end_point, end_line, _)) =
             self.point_of_offset(end_offset)?
         else {
             return Ok(vec![]);
         };
-        end_point.y += self.style.line_height;
-        if start_line == end_line {
-            Ok(vec![Rect::from_points(start_point, end_point)])
-        } else {
-            let mut rects =
-                Vec::with_capacity(end_line - start_line + 1);
-            let viewport_width = self.viewport.width();
-            rects.push(Rect::from_origin_size(
-                start_point,
-                (viewport_width, self.style.line_height)
-            ));
-            start_line += 1;
-            while start_line < end_line {
-                rects.push(Rect::from_origin_size(
-                    Point::new(0.0, self.height_of_line(start_line)),
-                    (viewport_width, self.style.line_height)
-                ));
-                start_line += 1;
-            }
-            rects.push(Rect::from_points(
-                Point::new(0.0, self.height_of_line(start_line)),
-                end_point
+        let start_bottom = start_point.y
+            + self.lines.row_height_of_line(start_line)?;
+        let end_bottom =
+            end_point.y + self.lines.row_height_of_line(end_line)?;
+        if start_point.y == end_point.y {
+            return Ok(vec![Rect::new(
+                start_point.x,
+                start_point.y,
+                end_point.x,
+                end_bottom
+            )]);
+        }
+        let text_x = self.gutter_width();
+        let right = self.viewport.x1.max(start_point.x);
+        let mut rects = vec![Rect::new(
+            start_point.x,
+            start_point.y,
+            right,
+            start_bottom
+        )];
+        // everything in between, only the part in viewport
+        let middle_top = start_bottom.max(self.viewport.y0);
+        let middle_bottom = end_point.y.min(self.viewport.y1);
+        if middle_top < middle_bottom {
+            rects.push(Rect::new(
+                text_x,
+                middle_top,
+                right,
+                middle_bottom
             ));
-            Ok(rects)
         }
+        rects.push(Rect::new(
+            text_x,
+            end_point.y,
+            end_point.x,
+            end_bottom
+        ));
+        Ok(rects)
     }
 
     // pub fn append_line(
@@ -544,34 +1423,49 @@ impl SimpleDoc {
         &mut self,
         lines: StyledText
     ) -> anyhow::Result<()> {
-        let lines = lines.to_lines()?;
-        self.lines.append_lines(
-            lines,
-            self.line_ending,
-            &self.style
-        )?;
+        self.append_task_lines(None, lines).map(|_| ())
+    }
 
+    /// append lines of `task`, see `TreePanelData::run_task`.
+    /// Returns the number of lines, blank lines being dropped.
+    pub fn append_task_lines(
+        &mut self,
+        task: Option<TaskId>,
+        lines: StyledText
+    ) -> anyhow::Result<usize> {
+        let mut lines = lines.to_lines()?;
+        lines.task = task;
+        let len = lines.lines.len();
+        self.lines.append_lines(lines, self.line_ending)?;
+        self.measure_viewport();
+        self.apply_pending_scroll();
         self.id.request_layout();
         self.id.request_paint();
-        if let Err(err) = self.auto_scroll(false) {
-            error!("{err:?}");
+        if self.is_following() {
+            if let Err(err) = self.auto_scroll(false) {
+                error!("{err:?}");
+            }
+        } else {
+            self.follow_tail.update(|x| {
+                if let FollowTail::Paused { new_lines, .. } = x {
+                    *new_lines += len;
+                }
+            });
         }
-        Ok(())
+        Ok(len)
     }
 
     fn offset_of_line(&self, line: usize) -> anyhow::Result<usize> {
-        self.lines.line_info()?.0.offset_of_line(line)
+        self.lines.line_info()?.rope.offset_of_line(line)
     }
 
     fn line_of_offset(&self, offset: usize) -> anyhow::Result<usize> {
-        Ok(self.lines.line_info()?.0.line_of_offset(offset))
+        Ok(self.lines.line_info()?.rope.line_of_offset(offset))
     }
 
     pub fn view_size(&self) -> Size {
-        match self
-            .lines
-            .visual_lines_size(self.viewport, self.style.line_height)
-        {
+        let text_x = self.gutter_width();
+        match self.lines.visual_lines_size(self.viewport, text_x) {
             Ok(size) => size,
             Err(err) => {
                 error!("{err:?}");
@@ -581,11 +1475,8 @@ impl SimpleDoc {
     }
 
     pub fn viewport_lines(&self) -> Vec<VisualLine> {
-        match self.lines.visual_lines(
-            self.viewport,
-            self.style.line_height,
-            self.style.fg_color
-        ) {
+        let hover = self.hover_hyperlink.get_untracked();
+        match self.lines.visual_lines(self.viewport, hover) {
             Ok(lines) => lines,
             Err(err) => {
                 error!("{err:?}");
@@ -598,43 +1489,197 @@ impl SimpleDoc {
         let viewport_size = viewport.size();
         // viewport_size.height -= self.style.line_height / 0.5;
         // viewport_size.width -= self.style.line_height * 1.5;
+        let width_changed = self.viewport.width() != viewport.width();
+        let scrolled_up = viewport.y0 < self.viewport.y0;
+        let scrolled_down = viewport.y0 > self.viewport.y0;
+        if viewport.origin() != self.viewport.origin() {
+            // the link moved away from under the tooltip
+            self.pointer_leave();
+        }
+        if viewport.y0 != self.viewport.y0 {
+            self.pending_scroll_y = None;
+        }
         self.viewport = viewport.with_size(viewport_size);
+        // only a vertical scroll changes the following
+        match self.at_bottom() {
+            Ok(true) if scrolled_down => {
+                if let FollowTail::Paused { pinned: false, .. } =
+                    self.follow_tail.get_untracked()
+                {
+                    self.follow_tail.set(FollowTail::Following);
+                }
+            },
+            Ok(false) if scrolled_up => self.pause_follow_tail(false),
+            Ok(_) => {},
+            Err(err) => error!("{err:?}")
+        }
+        if width_changed
+            && self.style.wrap_style == WrapStyle::EditorWidth
+        {
+            self.lines.set_wrap_width(self.wrap_width());
+            self.id.request_paint();
+        }
+        self.measure_viewport();
         // info!("update_viewport_by_scroll {:?} {:?}",
         // viewport.size(), self.viewport.size());
         self.id.request_layout();
     }
 
+    /// Switch colors, e.g. between `PanelTheme::light` and
+    /// `PanelTheme::dark`.
+    pub fn set_theme(&mut self, theme: PanelTheme) {
+        self.style.theme = theme;
+        self.gutter_layouts.get_mut().clear();
+        self.lines.set_style(self.style.clone());
+        self.id.request_paint();
+    }
+
+    pub fn set_wrap_style(&mut self, wrap_style: WrapStyle) {
+        self.style.wrap_style = wrap_style;
+        self.lines.set_wrap_width(self.wrap_width());
+        self.measure_viewport();
+        self.id.request_layout();
+        self.id.request_paint();
+    }
+
+    /// Shape the lines in the viewport whose rows are estimated,
+    /// see `Lines::measure_rows`.
+    fn measure_viewport(&mut self) {
+        if let Err(err) = self.lines.measure_rows(self.viewport) {
+            error!("{err:?}");
+        }
+    }
+
+    /// the width to wrap text at, `None` for no wrap
+    fn wrap_width(&self) -> Option<f32> {
+        match self.style.wrap_style {
+            WrapStyle::None => None,
+            WrapStyle::EditorWidth => {
+                let width =
+                    self.viewport.width() - self.gutter_width();
+                (width > 0.0).then_some(width as f32)
+            },
+            WrapStyle::WrapWidth => Some(self.style.wrap_width as f32)
+        }
+    }
+
+    /// Scroll so that `y` is the top of the viewport, as far as the
+    /// lines allow.
+    pub fn scroll_to_y(&self, y: f64) {
+        let height =
+            self.viewport.height().max(self.style.line_height);
+        self.id.scroll_to(Some(Rect::new(0.0, y, 1.0, y + height)));
+    }
+
+    /// Scroll to `y` once the lines are high enough to, e.g. a
+    /// restored position before the output arrives. Scrolling
+    /// meanwhile cancels it.
+    pub fn scroll_to_y_when_ready(&mut self, y: f64) {
+        self.pending_scroll_y = Some(y);
+        self.apply_pending_scroll();
+    }
+
+    fn apply_pending_scroll(&mut self) {
+        let Some(y) = self.pending_scroll_y else {
+            return;
+        };
+        let height = match self.lines.line_info() {
+            Ok(info) => info.height(),
+            Err(err) => {
+                error!("{err:?}");
+                return;
+            }
+        };
+        if height >= y + self.viewport.height() {
+            self.pending_scroll_y = None;
+            self.scroll_to_y(y);
+        }
+    }
+
+    /// Regroup the lines, showing all of them if the displayed
+    /// lines are not a group anymore.
+    pub fn set_grouping(&mut self, grouping: Rc<dyn Grouping>) {
+        self.lines.set_grouping(grouping, self.line_ending);
+        self.cursor.position = Position::None;
+        let display = self.display.get_untracked();
+        if self.lines.ropes.contains_key(&display) {
+            self.lines.display(display);
+            self.measure_viewport();
+            self.id.request_layout();
+            self.id.request_paint();
+        } else {
+            self.update_display(DisplayId::All);
+        }
+    }
+
     pub fn update_display(&mut self, id: DisplayId) {
         // info!("update_display {:?}", id);
+        if self.display.get_untracked() != id {
+            self.display.set(id.clone());
+        }
         self.lines.display(id);
+        self.measure_viewport();
         self.id.request_layout();
         self.id.request_paint();
-        self.id.scroll_to(Some(Rect::new(
-            0.0,
-            0.0,
-            self.style.line_height,
-            self.style.line_height
-        )));
-        // self.auto_scroll(true);
+        if self.is_following() {
+            if let Err(err) = self.auto_scroll(true) {
+                error!("{err:?}");
+            }
+        } else {
+            self.id.scroll_to(Some(Rect::new(
+                0.0,
+                0.0,
+                self.style.line_height,
+                self.style.line_height
+            )));
+        }
     }
 
     fn auto_scroll(&self, force: bool) -> anyhow::Result<()> {
-        if self.auto_scroll || force {
-            let len = self.lines.line_info()?.0.len();
-            let line = self.line_of_offset(len)?;
-            let rect = Rect::from_origin_size(
-                Point::new(
-                    self.viewport.x0,
-                    self.height_of_line(line)
-                ),
-                Size::new(
-                    self.style.line_height,
-                    self.style.line_height
-                )
+        if self.is_following() || force {
+            let bottom = self.lines.line_info()?.height();
+            let rect = Rect::new(
+                self.viewport.x0,
+                bottom - self.style.line_height,
+                self.viewport.x0 + self.style.line_height,
+                bottom
             );
-            // debug!("auto_scroll {rect:?} len={len} line={line}",);
+            // debug!("auto_scroll {rect:?}");
             self.id.scroll_to(Some(rect));
         }
         Ok(())
     }
 }
+
+/// Open `target`, a path or an url, with the default application.
+///
+/// `target` comes from the output, so it never goes through a shell:
+/// `open` calls `ShellExecuteW` on windows.
+pub fn open_with_system(target: &str) -> std::io::Result<()> {
+    open::that_detached(target)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{TimeDelta, TimeZone};
+
+    #[test]
+    fn test_timestamp_format() {
+        let started =
+            Local.with_ymd_and_hms(2025, 1, 2, 3, 4, 5).unwrap();
+        let time = started + TimeDelta::milliseconds(61_007);
+        let relative = Timestamp::Relative.format(time, started);
+        let absolute = Timestamp::Absolute.format(time, started);
+        assert_eq!("+01:01.007", relative);
+        assert_eq!("03:05:06.007", absolute);
+        assert_eq!(
+            "+60:00.000",
+            Timestamp::Relative
+                .format(started + TimeDelta::hours(1), started)
+        );
+        // the gutter column fits them
+        assert_eq!(Timestamp::Relative.chars(), relative.len());
+        assert_eq!(Timestamp::Absolute.chars(), absolute.len());
+    }
+}
@@ -30,6 +30,19 @@ impl Cursor {
         })
     }
 
+    /// Move the caret to `offset`. With `extend` the current anchor
+    /// is kept and a region is created, like shift+click.
+    pub fn move_to(&mut self, offset: usize, extend: bool) {
+        self.position = if extend {
+            Position::Region {
+                start: self.start().unwrap_or(offset),
+                end:   offset
+            }
+        } else {
+            Position::Caret(offset)
+        };
+    }
+
     pub fn region(&self) -> Option<(usize, usize)> {
         if let Position::Region { start, end } = self.position {
             match start.cmp(&end) {
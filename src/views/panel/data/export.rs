@@ -0,0 +1,189 @@
+use ansi_to_style::TextStyle;
+use floem::peniko::Color;
+use std::{fmt::Write, ops::Range};
+
+/// How the panel text is put on the clipboard.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CopyFormat {
+    PlainText,
+    /// SGR escapes, for pasting into a terminal
+    Ansi
+}
+
+/// `content[range]` with the parts of `styles` it covers as SGR
+/// escapes.
+pub(crate) fn to_ansi(
+    content: &str,
+    styles: &[TextStyle],
+    range: Range<usize>
+) -> String {
+    let mut rs = String::with_capacity(range.len());
+    for (text, style) in segments(content, styles, range) {
+        let Some(style) = style else {
+            rs.push_str(text);
+            continue;
+        };
+        let mut codes = vec![];
+        if style.bold {
+            codes.push("1".to_string());
+        }
+        if style.italic {
+            codes.push("3".to_string());
+        }
+        if style.underline {
+            codes.push("4".to_string());
+        }
+        if let Some(color) = style.fg_color {
+            let [r, g, b] = rgb(color);
+            codes.push(format!("38;2;{r};{g};{b}"));
+        }
+        if let Some(color) = style.bg_color {
+            let [r, g, b] = rgb(color);
+            codes.push(format!("48;2;{r};{g};{b}"));
+        }
+        if codes.is_empty() {
+            rs.push_str(text);
+        } else {
+            let codes = codes.join(";");
+            let _ = write!(rs, "\x1b[{codes}m{text}\x1b[0m");
+        }
+    }
+    rs
+}
+
+/// Split `content[range]` into runs that are unstyled or covered by
+/// one style. Styles are assumed not to overlap, as produced by
+/// `ansi_to_style`.
+fn segments<'a>(
+    content: &'a str,
+    styles: &'a [TextStyle],
+    range: Range<usize>
+) -> Vec<(&'a str, Option<&'a TextStyle>)> {
+    let end = range.end.min(content.len());
+    let mut start = range.start.min(end);
+    let mut styles: Vec<&TextStyle> = styles
+        .iter()
+        .filter(|x| x.range.start < end && x.range.end > start)
+        .collect();
+    styles.sort_by_key(|x| x.range.start);
+    let mut rs = vec![];
+    for style in styles {
+        let style_start = style.range.start.max(start);
+        let style_end = style.range.end.min(end);
+        if style_start >= style_end {
+            continue;
+        }
+        if start < style_start {
+            rs.push((slice(content, start..style_start), None));
+        }
+        let text = slice(content, style_start..style_end);
+        rs.push((text, Some(style)));
+        start = style_end;
+    }
+    if start < end {
+        rs.push((slice(content, start..end), None));
+    }
+    rs
+}
+
+fn slice(content: &str, range: Range<usize>) -> &str {
+    content.get(range).unwrap_or_default()
+}
+
+fn rgb(color: Color) -> [u8; 3] {
+    let rgba = color.to_rgba8();
+    [rgba.r, rgba.g, rgba.b]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn style(range: Range<usize>) -> TextStyle {
+        TextStyle {
+            range,
+            bold: true,
+            ..Default::default()
+        }
+    }
+
+    /// (text, range of its style)
+    fn runs(
+        content: &str,
+        styles: &[TextStyle],
+        range: Range<usize>
+    ) -> Vec<(String, Option<Range<usize>>)> {
+        segments(content, styles, range)
+            .into_iter()
+            .map(|(text, style)| {
+                (text.to_string(), style.map(|x| x.range.clone()))
+            })
+            .collect()
+    }
+
+    fn run(
+        text: &str,
+        style: Option<Range<usize>>
+    ) -> (String, Option<Range<usize>>) {
+        (text.to_string(), style)
+    }
+
+    #[test]
+    fn test_segments() {
+        let content = "warning: unused variable";
+        assert_eq!(
+            vec![run(content, None)],
+            runs(content, &[], 0..24)
+        );
+
+        let styles = [style(9..15), style(0..7)];
+        assert_eq!(
+            vec![
+                run("warning", Some(0..7)),
+                run(": ", None),
+                run("unused", Some(9..15)),
+                run(" variable", None)
+            ],
+            runs(content, &styles, 0..24)
+        );
+        // the range cuts the styles, styles outside it are dropped
+        assert_eq!(
+            vec![
+                run("ing", Some(0..7)),
+                run(": ", None),
+                run("u", Some(9..15))
+            ],
+            runs(content, &styles, 4..10)
+        );
+        assert_eq!(
+            vec![run("nus", Some(9..15))],
+            runs(content, &styles, 10..13)
+        );
+        assert_eq!(
+            vec![run("var", None)],
+            runs(content, &styles, 16..19)
+        );
+        // past the end of the content
+        assert_eq!(
+            vec![run("able", None)],
+            runs(content, &styles, 20..40)
+        );
+        assert!(runs(content, &styles, 30..40).is_empty());
+    }
+
+    #[test]
+    fn test_segments_overlap() {
+        // overlapping styles are not expected, the later one starts
+        // where the earlier one ends
+        let content = "abcdefgh";
+        let styles = [style(2..6), style(0..4)];
+        assert_eq!(
+            vec![
+                run("abcd", Some(0..4)),
+                run("ef", Some(2..6)),
+                run("gh", None)
+            ],
+            runs(content, &styles, 0..8)
+        );
+    }
+}
@@ -0,0 +1,222 @@
+use crate::views::{
+    panel::{ErrLevel, Segment, TextSrc},
+    tree_with_panel::data::Level
+};
+use std::fmt::Debug;
+
+/// Turns the source of a `StyledText` into its node in the tree, a
+/// `DisplayId::Path`. The text is shown in `DisplayId::All` and in
+/// every node down to its node.
+pub trait Grouping: Debug {
+    fn path(&self, src: &TextSrc, level: Level) -> Vec<Segment>;
+}
+
+/// The groupings of the tree.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum GroupBy {
+    /// crate → file, stderr errors and process tags on their own
+    #[default]
+    CrateFile,
+    /// crate → module → file, the modules being the directories of
+    /// the file
+    CrateModuleFile,
+    /// errors, warnings, ... → crate
+    SeverityCrate,
+    /// one node per file, without crates
+    File
+}
+
+impl GroupBy {
+    pub const ALL: [GroupBy; 4] = [
+        GroupBy::CrateFile,
+        GroupBy::CrateModuleFile,
+        GroupBy::SeverityCrate,
+        GroupBy::File
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            GroupBy::CrateFile => "Crate → File",
+            GroupBy::CrateModuleFile => "Crate → Module → File",
+            GroupBy::SeverityCrate => "Severity → Crate",
+            GroupBy::File => "File"
+        }
+    }
+
+    fn crate_path(
+        &self,
+        crate_name: &str,
+        file: Option<&str>,
+        level: Level
+    ) -> Vec<Segment> {
+        let crate_segment = Segment::Crate(crate_name.to_string());
+        let file_segment = file.map(|x| Segment::File(x.to_string()));
+        match self {
+            GroupBy::CrateFile => std::iter::once(crate_segment)
+                .chain(file_segment)
+                .collect(),
+            GroupBy::CrateModuleFile => {
+                let modules =
+                    file.map(module_path).unwrap_or_default();
+                std::iter::once(crate_segment)
+                    .chain(modules)
+                    .chain(file_segment)
+                    .collect()
+            },
+            GroupBy::SeverityCrate => {
+                vec![Segment::Level(level), crate_segment]
+            },
+            GroupBy::File => file_segment.into_iter().collect()
+        }
+    }
+}
+
+impl Grouping for GroupBy {
+    fn path(&self, src: &TextSrc, level: Level) -> Vec<Segment> {
+        match src {
+            TextSrc::StdOut {
+                crate_name, file, ..
+            } => self.crate_path(crate_name, file.as_deref(), level),
+            _ if *self == GroupBy::SeverityCrate => match level {
+                Level::None => vec![],
+                level => vec![Segment::Level(level)]
+            },
+            TextSrc::StdErr { level: err_level } => match err_level {
+                ErrLevel::Error => vec![Segment::Level(Level::Error)],
+                ErrLevel::Other => vec![]
+            },
+            TextSrc::Process { tag, .. } => {
+                tag.as_deref().map(tag_path).unwrap_or_default()
+            },
+        }
+    }
+}
+
+/// `suite::module::test` nests the test under its module and suite
+fn tag_path(tag: &str) -> Vec<Segment> {
+    tag.split("::")
+        .filter(|x| !x.is_empty())
+        .map(|x| Segment::Tag(x.to_string()))
+        .collect()
+}
+
+/// the directories of `file` below `src`, e.g. `views`, `panel` of
+/// `src/views/panel/data.rs`
+fn module_path(file: &str) -> Vec<Segment> {
+    let mut dirs: Vec<&str> = file.split(['/', '\\']).collect();
+    dirs.pop();
+    let start =
+        dirs.iter().position(|x| *x == "src").map_or(0, |x| x + 1);
+    dirs[start..]
+        .iter()
+        .map(|x| Segment::Module(x.to_string()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cargo_metadata::PackageId;
+
+    fn stdout(file: Option<&str>) -> TextSrc {
+        TextSrc::StdOut {
+            package_id: PackageId {
+                repr: "path+file:///dir/app#0.1.0".to_string()
+            },
+            crate_name: "app".to_string(),
+            file:       file.map(str::to_string)
+        }
+    }
+
+    fn process(tag: Option<&str>) -> TextSrc {
+        TextSrc::Process {
+            name: "test".to_string(),
+            tag:  tag.map(str::to_string)
+        }
+    }
+
+    fn app() -> Segment {
+        Segment::Crate("app".to_string())
+    }
+
+    fn tag(name: &str) -> Segment {
+        Segment::Tag(name.to_string())
+    }
+
+    #[test]
+    fn test_path() {
+        use GroupBy::*;
+        let file = Segment::File("src/views/icon.rs".to_string());
+        let views = Segment::Module("views".to_string());
+        let src = stdout(Some("src/views/icon.rs"));
+        let path = |x: GroupBy| x.path(&src, Level::Warn);
+        assert_eq!(vec![app(), file.clone()], path(CrateFile));
+        assert_eq!(
+            vec![app(), views, file.clone()],
+            path(CrateModuleFile)
+        );
+        assert_eq!(
+            vec![Segment::Level(Level::Warn), app()],
+            path(SeverityCrate)
+        );
+        assert_eq!(vec![file], path(File));
+
+        let src = stdout(None);
+        assert_eq!(vec![app()], CrateFile.path(&src, Level::Warn));
+        assert!(File.path(&src, Level::Warn).is_empty());
+    }
+
+    #[test]
+    fn test_path_of_stderr_and_process() {
+        use GroupBy::*;
+        let error = TextSrc::StdErr {
+            level: ErrLevel::Error
+        };
+        let other = TextSrc::StdErr {
+            level: ErrLevel::Other
+        };
+        let errors = vec![Segment::Level(Level::Error)];
+        let warnings = vec![Segment::Level(Level::Warn)];
+        assert_eq!(errors, CrateFile.path(&error, Level::None));
+        assert!(CrateFile.path(&other, Level::Warn).is_empty());
+        assert_eq!(warnings, SeverityCrate.path(&other, Level::Warn));
+        assert!(SeverityCrate.path(&other, Level::None).is_empty());
+
+        let test = process(Some("suite::test"));
+        assert_eq!(
+            vec![tag("suite"), tag("test")],
+            CrateFile.path(&test, Level::Success)
+        );
+        assert!(File.path(&process(None), Level::None).is_empty());
+    }
+
+    #[test]
+    fn test_module_path() {
+        let module = |name: &str| Segment::Module(name.to_string());
+        assert_eq!(
+            vec![module("views"), module("panel")],
+            module_path("src/views/panel/data.rs")
+        );
+        // below the `src` of a workspace member
+        assert_eq!(
+            vec![module("views")],
+            module_path("libs/doc/src/views/lines.rs")
+        );
+        assert_eq!(
+            vec![module("tests"), module("it")],
+            module_path("tests\\it\\main.rs")
+        );
+        assert!(module_path("src/lib.rs").is_empty());
+        assert!(module_path("build.rs").is_empty());
+    }
+
+    #[test]
+    fn test_tag_path() {
+        assert_eq!(
+            vec![tag("suite"), tag("module"), tag("test")],
+            tag_path("suite::module::test")
+        );
+        assert_eq!(vec![tag("test")], tag_path("::test::"));
+        assert!(tag_path("").is_empty());
+    }
+}
@@ -0,0 +1,218 @@
+use std::ops::Range;
+
+/// The heights of the lines in a Fenwick tree, so that the top of a
+/// line, the line at a y and changing one height are all `O(log n)`.
+///
+/// Unlike `doc::lens::Lens` it is appended to one line at a time,
+/// which is how output arrives.
+#[derive(Clone, Debug, Default)]
+pub struct HeightIndex {
+    /// entry `i` holds the sum of the heights of
+    /// `i + 1 - lowbit(i + 1)..=i`
+    tree:    Vec<f64>,
+    heights: Vec<f64>
+}
+
+impl HeightIndex {
+    pub fn from_heights(heights: impl Iterator<Item = f64>) -> Self {
+        let heights: Vec<f64> = heights.collect();
+        let mut tree = heights.clone();
+        for index in 0..tree.len() {
+            let parent = index + lowbit(index + 1);
+            if parent < tree.len() {
+                tree[parent] += tree[index];
+            }
+        }
+        Self { tree, heights }
+    }
+
+    pub fn push(&mut self, height: f64) {
+        let index = self.tree.len();
+        let mut sum = height;
+        let mut child = 1;
+        while child < lowbit(index + 1) {
+            sum += self.tree[index - child];
+            child <<= 1;
+        }
+        self.tree.push(sum);
+        self.heights.push(height);
+    }
+
+    pub fn len(&self) -> usize {
+        self.heights.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.heights.is_empty()
+    }
+
+    /// the sum of all heights
+    pub fn total(&self) -> f64 {
+        self.top(self.len())
+    }
+
+    /// The top of entry `index`, `total` if it is past the end.
+    pub fn top(&self, index: usize) -> f64 {
+        let mut end = index.min(self.len());
+        let mut top = 0.0;
+        while end > 0 {
+            top += self.tree[end - 1];
+            end -= lowbit(end);
+        }
+        top
+    }
+
+    /// the height of entry `index`
+    pub fn height(&self, index: usize) -> f64 {
+        self.heights.get(index).copied().unwrap_or_default()
+    }
+
+    /// Replace the heights of some entries, `heights` being
+    /// `(index, height)`. The entries below move by the difference.
+    pub fn set_heights(&mut self, heights: &[(usize, f64)]) {
+        for (index, height) in heights {
+            let Some(old) = self.heights.get_mut(*index) else {
+                continue;
+            };
+            let delta = height - *old;
+            *old = *height;
+            let mut end = index + 1;
+            while end <= self.tree.len() {
+                self.tree[end - 1] += delta;
+                end += lowbit(end);
+            }
+        }
+    }
+
+    /// The entry covering `y`, `None` if `y` is below the last one.
+    /// Entries of height 0 cover nothing.
+    pub fn index_of_y(&self, y: f64) -> Option<usize> {
+        if y >= self.total() {
+            return None;
+        }
+        Some(self.partition_point(|top| top <= y).max(1) - 1)
+    }
+
+    /// The entries intersecting `y0..y1`, empty below the last one.
+    pub fn range_of_y(&self, y0: f64, y1: f64) -> Range<usize> {
+        if y0 >= self.total() {
+            return self.len()..self.len();
+        }
+        let start = self.partition_point(|top| top <= y0).max(1) - 1;
+        let end = self.partition_point(|top| top < y1).max(start);
+        start..end
+    }
+
+    /// Like `slice::partition_point` over the tops of the entries,
+    /// `pred` holding for the tops up to some y.
+    fn partition_point(&self, pred: impl Fn(f64) -> bool) -> usize {
+        if self.is_empty() || !pred(0.0) {
+            return 0;
+        }
+        // the most entries whose sum still satisfies `pred`
+        let (mut count, mut top) = (0, 0.0);
+        let mut step = 1 << self.len().ilog2();
+        while step > 0 {
+            let next = count + step;
+            if next <= self.len() && pred(top + self.tree[next - 1]) {
+                count = next;
+                top += self.tree[next - 1];
+            }
+            step >>= 1;
+        }
+        (count + 1).min(self.len())
+    }
+}
+
+/// the lowest set bit of `index`
+fn lowbit(index: usize) -> usize {
+    index & index.wrapping_neg()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn index() -> HeightIndex {
+        let heights = [10.0, 20.0, 10.0, 30.0];
+        HeightIndex::from_heights(heights.into_iter())
+    }
+
+    #[test]
+    fn test_index_of_y() {
+        let index = index();
+        assert_eq!(4, index.len());
+        assert_eq!(70.0, index.total());
+        assert_eq!(30.0, index.top(2));
+        assert_eq!(70.0, index.top(4));
+
+        assert_eq!(Some(0), index.index_of_y(-5.0));
+        assert_eq!(Some(0), index.index_of_y(0.0));
+        assert_eq!(Some(0), index.index_of_y(9.9));
+        assert_eq!(Some(1), index.index_of_y(10.0));
+        assert_eq!(Some(1), index.index_of_y(29.9));
+        assert_eq!(Some(2), index.index_of_y(30.0));
+        assert_eq!(Some(3), index.index_of_y(40.0));
+        assert_eq!(Some(3), index.index_of_y(69.9));
+        assert_eq!(None, index.index_of_y(70.0));
+        assert_eq!(None, HeightIndex::default().index_of_y(0.0));
+    }
+
+    #[test]
+    fn test_range_of_y() {
+        let index = index();
+        assert_eq!(0..1, index.range_of_y(0.0, 10.0));
+        assert_eq!(0..3, index.range_of_y(5.0, 35.0));
+        assert_eq!(1..2, index.range_of_y(10.0, 30.0));
+        assert_eq!(0..4, index.range_of_y(-10.0, 100.0));
+        assert_eq!(3..4, index.range_of_y(65.0, 100.0));
+        assert_eq!(1..1, index.range_of_y(10.0, 10.0));
+        assert_eq!(4..4, index.range_of_y(70.0, 100.0));
+        let empty = HeightIndex::default();
+        assert_eq!(0..0, empty.range_of_y(0.0, 10.0));
+    }
+
+    #[test]
+    fn test_set_heights() {
+        let mut index = index();
+        index.set_heights(&[(1, 5.0), (3, 0.0)]);
+        assert_eq!(25.0, index.total());
+        assert_eq!(
+            vec![10.0, 5.0, 10.0, 0.0],
+            (0..4).map(|x| index.height(x)).collect::<Vec<_>>()
+        );
+        assert_eq!(15.0, index.top(2));
+        assert_eq!(Some(2), index.index_of_y(15.0));
+        index.set_heights(&[]);
+        assert_eq!(25.0, index.total());
+    }
+
+    #[test]
+    fn test_zero_heights() {
+        // hidden entries have no height and are never at a y
+        let heights = [10.0, 0.0, 0.0, 20.0, 0.0];
+        let index = HeightIndex::from_heights(heights.into_iter());
+        assert_eq!(30.0, index.total());
+        assert_eq!(10.0, index.top(1));
+        assert_eq!(10.0, index.top(3));
+        assert_eq!(Some(0), index.index_of_y(9.9));
+        assert_eq!(Some(3), index.index_of_y(10.0));
+        assert_eq!(None, index.index_of_y(30.0));
+        assert_eq!(0..4, index.range_of_y(5.0, 15.0));
+    }
+
+    #[test]
+    fn test_push_matches_from_heights() {
+        let heights: Vec<f64> =
+            (0..37).map(|x| (x % 5) as f64 * 3.0).collect();
+        let mut pushed = HeightIndex::default();
+        heights.iter().for_each(|x| pushed.push(*x));
+        let built = HeightIndex::from_heights(heights.into_iter());
+        for index in 0..=37 {
+            assert_eq!(built.top(index), pushed.top(index));
+        }
+        pushed.set_heights(&[(36, 1.0), (3, 2.0)]);
+        assert_eq!(built.total() - 2.0 - 7.0, pushed.total());
+        assert_eq!(built.top(3) + 2.0, pushed.top(4));
+    }
+}
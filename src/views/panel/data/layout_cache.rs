@@ -0,0 +1,54 @@
+use std::{collections::HashMap, hash::Hash};
+
+/// Shaped layouts of recently visible lines, keyed by text index, or
+/// by `K`, e.g. the text of a gutter column.
+///
+/// Evicts the least recently used half once `capacity` is exceeded,
+/// so the cost of an eviction is amortized over many inserts.
+#[derive(Clone, Debug)]
+pub(crate) struct LayoutCache<T, K = usize> {
+    capacity: usize,
+    tick:     u64,
+    layouts:  HashMap<K, (u64, T)>
+}
+
+impl<T: Clone, K: Hash + Eq> LayoutCache<T, K> {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            tick:     0,
+            layouts:  HashMap::with_capacity(capacity)
+        }
+    }
+
+    pub fn get(&mut self, key: &K) -> Option<T> {
+        self.tick += 1;
+        let tick = self.tick;
+        self.layouts.get_mut(key).map(|(used, layout)| {
+            *used = tick;
+            layout.clone()
+        })
+    }
+
+    pub fn insert(&mut self, key: K, layout: T) {
+        self.tick += 1;
+        self.layouts.insert(key, (self.tick, layout));
+        if self.layouts.len() > self.capacity {
+            self.evict();
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.layouts.clear();
+    }
+
+    fn evict(&mut self) {
+        let mut ticks: Vec<u64> =
+            self.layouts.values().map(|(used, _)| *used).collect();
+        let keep = self.capacity / 2;
+        let index = ticks.len() - keep - 1;
+        let (_, cutoff, _) = ticks.select_nth_unstable(index);
+        let cutoff = *cutoff;
+        self.layouts.retain(|_, (used, _)| *used > cutoff);
+    }
+}
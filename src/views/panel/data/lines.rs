@@ -1,10 +1,17 @@
+use super::{
+    export::{CopyFormat, to_ansi},
+    grouping::{GroupBy, Grouping},
+    height_index::HeightIndex,
+    layout_cache::LayoutCache
+};
 use crate::views::{
     panel::DocStyle,
-    tree_with_panel::data::{StyledLines, VisualLine}
+    tree_with_panel::data::{Level, StyledLines, VisualLine}
 };
 use ansi_to_style::TextStyle;
 use anyhow::{Result, anyhow};
 use cargo_metadata::PackageId;
+use chrono::{DateTime, Local};
 use doc::{
     hit_position_aff,
     lines::{layout::TextLayout, line_ending::LineEnding}
@@ -13,12 +20,20 @@ use floem::{
     kurbo::{Point, Rect, Size},
     peniko::Color,
     text::{
-        Attrs, AttrsList, FONT_SYSTEM, FamilyOwned, Style, Weight
+        Attrs, AttrsList, FONT_SYSTEM, FamilyOwned, Style, Weight,
+        Wrap
     }
 };
 use lapce_xi_rope::Rope;
 use log::{error, warn};
-use std::{borrow::Cow, collections::HashMap, ops::Range};
+use serde::{Deserialize, Serialize};
+use std::{
+    borrow::Cow, cell::RefCell, collections::HashMap, ops::Range,
+    path::PathBuf, rc::Rc
+};
+
+/// How many shaped lines `Lines` keeps around.
+const LAYOUT_CACHE_CAPACITY: usize = 2048;
 
 #[derive(Clone, Debug)]
 pub enum Hyperlink {
@@ -43,6 +58,59 @@ impl Hyperlink {
         }
     }
 
+    /// The absolute `path:line:column` of a file, or the url.
+    /// Relative paths, as cargo reports them, are resolved against
+    /// the current directory.
+    pub fn target(&self) -> String {
+        match self {
+            Hyperlink::File {
+                src, line, column, ..
+            } => {
+                let path = std::path::absolute(src)
+                    .map(|x| x.display().to_string())
+                    .unwrap_or_else(|_| src.clone());
+                match column {
+                    Some(column) => format!("{path}:{line}:{column}"),
+                    None => format!("{path}:{line}")
+                }
+            },
+            Hyperlink::Url { url, .. } => url.clone()
+        }
+    }
+
+    /// the file of a file link
+    pub fn src(&self) -> Option<&str> {
+        match self {
+            Hyperlink::File { src, .. } => Some(src),
+            Hyperlink::Url { .. } => None
+        }
+    }
+
+    /// `context` lines of `content`, the text of `src`, around the
+    /// referenced line, numbered and with the referenced one marked.
+    /// `None` for urls.
+    pub fn preview(
+        &self,
+        content: &str,
+        context: usize
+    ) -> Option<String> {
+        let Hyperlink::File { line, .. } = self else {
+            return None;
+        };
+        let first = line.saturating_sub(context + 1);
+        let preview: Vec<String> = content
+            .lines()
+            .enumerate()
+            .skip(first)
+            .take(context * 2 + 1)
+            .map(|(index, text)| {
+                let mark = if index + 1 == *line { '>' } else { ' ' };
+                format!("{mark}{:>5} | {text}", index + 1)
+            })
+            .collect();
+        (!preview.is_empty()).then(|| preview.join("\n"))
+    }
+
     pub fn range_mut(&mut self, new_range: Range<usize>) {
         match self {
             Hyperlink::File { range, .. } => {
@@ -59,162 +127,664 @@ impl Hyperlink {
 pub struct Lines {
     // pub rope:             Rope,
     pub display_strategy: DisplayId,
-    pub ropes: HashMap<
-        DisplayId,
-        (Rope, Vec<SimpleLine>, Vec<SimpleHyperlink>)
-    >,
+    pub ropes:            HashMap<DisplayId, DisplayLines>,
     // pub visual_line:      Vec<SimpleLine>,
     // pub visual_links:     Vec<SimpleHyperlink>,
     pub hyperlinks:       Vec<Hyperlink>,
-    pub texts:            Vec<TextLayout>
+    /// raw text of every line, shaped on demand
+    pub texts:            Vec<LineText>,
+    /// `None`: no soft wrap
+    pub wrap_width:       Option<f32>,
+    style:                DocStyle,
+    /// `DocStyle::char_width`, to estimate the rows of wrapped text
+    char_width:           f64,
+    /// arrival of the first `StyledText`
+    pub started:          Option<DateTime<Local>>,
+    /// the displays of a `StyledText` besides `DisplayId::All`
+    grouping:             Rc<dyn Grouping>,
+    layouts:              RefCell<LayoutCache<Rc<TextLayout>>>,
+    snippets:             RefCell<LayoutCache<Rc<Vec<TextLayout>>>>
 }
 
 impl Default for Lines {
     fn default() -> Self {
-        let mut ropes = HashMap::new();
-        ropes.insert(DisplayId::All, ("".into(), vec![], vec![]));
-        Self {
-            display_strategy: Default::default(),
-            ropes,
-            hyperlinks: vec![],
-            texts: vec![]
-        }
+        Self::new(DocStyle::default())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct LineText {
+    pub content:     String,
+    pub styles:      Vec<TextStyle>,
+    pub decorations: Vec<Decoration>
+}
+
+impl LineText {
+    /// the font scale of a header, 1.0 otherwise
+    pub fn scale(&self) -> f32 {
+        self.decorations
+            .iter()
+            .find_map(|x| match x {
+                Decoration::Header(scale) => Some(*scale),
+                _ => None
+            })
+            .unwrap_or(1.0)
+    }
+
+    pub fn snippet(&self) -> Option<&str> {
+        self.decorations.iter().find_map(|x| match x {
+            Decoration::Snippet(snippet) => Some(snippet.as_str()),
+            _ => None
+        })
+    }
+}
+
+/// Extra rendering of a `StyledText`. Header and separator apply to
+/// its first line, snippet to its last.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Decoration {
+    /// render the line with the font scaled by the factor
+    Header(f32),
+    /// a horizontal rule above the line, e.g. between runs
+    Separator,
+    /// a code snippet shown in a box below the line
+    Snippet(String)
+}
+
+impl Decoration {
+    pub fn is_below(&self) -> bool {
+        matches!(self, Decoration::Snippet(_))
     }
 }
 
-#[derive(Debug, Clone, Hash, Default, Eq, PartialEq)]
+/// The underline of a hyperlink, x relative to the text.
+#[derive(Debug, Clone)]
+pub struct VisualHyperlink {
+    pub start:   Point,
+    pub end:     Point,
+    pub color:   Color,
+    /// under the pointer, painted thicker
+    pub hovered: bool
+}
+
+/// A decoration positioned in the viewport, x relative to the text.
+#[derive(Debug, Clone)]
+pub enum VisualDecoration {
+    /// a horizontal rule at y
+    Rule(f64),
+    /// the box of a snippet and one layout per snippet line
+    Snippet(Rect, Rc<Vec<TextLayout>>)
+}
+
+#[derive(
+    Debug,
+    Clone,
+    Hash,
+    Default,
+    Eq,
+    PartialEq,
+    PartialOrd,
+    Ord,
+    Serialize,
+    Deserialize,
+)]
 pub enum DisplayId {
     #[default]
     All,
-    Error,
-    Crate {
-        crate_name: String
-    },
-    CrateFile {
-        crate_name: String,
-        file_name:  String
+    /// a node of the tree, below the nodes of the shorter paths,
+    /// e.g. `[Crate, Module, File]`. See `Grouping`.
+    Path(Vec<Segment>),
+    /// `id` within the lines of `task`. `DisplayId::All` in a task
+    /// is all of its lines.
+    Task { task: TaskId, id: Box<DisplayId> }
+}
+
+/// One level of a `DisplayId::Path`.
+#[derive(
+    Debug,
+    Clone,
+    Hash,
+    Eq,
+    PartialEq,
+    PartialOrd,
+    Ord,
+    Serialize,
+    Deserialize,
+)]
+pub enum Segment {
+    Crate(String),
+    Module(String),
+    File(String),
+    Level(Level),
+    /// a tag of a process, e.g. a test suite or a test
+    Tag(String)
+}
+
+impl Segment {
+    pub fn head(&self) -> String {
+        match self {
+            Segment::Crate(name)
+            | Segment::Module(name)
+            | Segment::File(name)
+            | Segment::Tag(name) => name.clone(),
+            Segment::Level(level) => level.label().to_string()
+        }
     }
 }
 
+/// A task of a `TreePanelData`, e.g. `cargo clippy` running next to
+/// `cargo test`.
+#[derive(
+    Debug,
+    Clone,
+    Hash,
+    Eq,
+    PartialEq,
+    PartialOrd,
+    Ord,
+    Serialize,
+    Deserialize,
+)]
+pub struct TaskId {
+    pub index: usize,
+    pub name:  String
+}
+
 impl DisplayId {
+    /// The nodes from the top down to the node of `path`: one per
+    /// prefix of `path`.
+    pub fn ancestors(path: &[Segment]) -> Vec<DisplayId> {
+        (1..=path.len())
+            .map(|x| DisplayId::Path(path[..x].to_vec()))
+            .collect()
+    }
+
+    /// whether `other` is this node or below it in the tree
+    pub fn contains(&self, other: &DisplayId) -> bool {
+        match (self, other) {
+            (DisplayId::All, _) => true,
+            (DisplayId::Path(path), DisplayId::Path(other)) => {
+                other.starts_with(path)
+            },
+            (
+                DisplayId::Task { task, id },
+                DisplayId::Task {
+                    task: other_task,
+                    id: other
+                }
+            ) => task == other_task && id.contains(other),
+            _ => false
+        }
+    }
+
+    /// the last segment of the path, in a task or not
+    pub fn segment(&self) -> Option<&Segment> {
+        match self {
+            DisplayId::All => None,
+            DisplayId::Path(path) => path.last(),
+            DisplayId::Task { id, .. } => id.segment()
+        }
+    }
+
+    /// this id in the namespace of `task`
+    pub fn in_task(self, task: &TaskId) -> Self {
+        DisplayId::Task {
+            task: task.clone(),
+            id:   Box::new(self)
+        }
+    }
+
+    pub fn task(&self) -> Option<&TaskId> {
+        match self {
+            DisplayId::Task { task, .. } => Some(task),
+            _ => None
+        }
+    }
+
     pub fn head(&self) -> String {
         match self {
-            DisplayId::All => "Run Cargo Command".to_string(),
-            DisplayId::Error => "Error".to_string(),
-            DisplayId::Crate { crate_name } => {
-                format!("Compiling {}", crate_name.clone())
-            },
-            DisplayId::CrateFile { file_name, .. } => {
-                file_name.clone()
+            DisplayId::All => "Output".to_string(),
+            DisplayId::Path(path) => match path.as_slice() {
+                [Segment::Crate(name)] => format!("Compiling {name}"),
+                [.., last] => last.head(),
+                [] => "Output".to_string()
             },
+            DisplayId::Task { task, id } => match id.as_ref() {
+                DisplayId::All => task.name.clone(),
+                id => id.head()
+            }
         }
     }
 }
 
+#[derive(Clone, Debug, Default)]
+pub struct DisplayLines {
+    pub rope:    Rope,
+    pub lines:   Vec<SimpleLine>,
+    /// one block per appended `StyledText`, in line order
+    pub blocks:  Vec<DiagnosticBlock>,
+    /// the heights of `lines`, 0 for the lines hidden by a folded
+    /// block
+    pub heights: HeightIndex
+}
+
+impl DisplayLines {
+    /// The top of `line`. A line hidden by a folded block maps to
+    /// the top of the block header.
+    pub fn top_of_line(&self, line: usize) -> f64 {
+        let line = self
+            .folded_block_of(line)
+            .map(|x| x.start_line)
+            .unwrap_or(line);
+        self.heights.top(line)
+    }
+
+    /// (line, top of the line)
+    pub fn line_of_y(&self, y: f64) -> Option<(usize, f64)> {
+        let line = self.heights.index_of_y(y)?;
+        Some((line, self.heights.top(line)))
+    }
+
+    /// the height of all visible lines
+    pub fn height(&self) -> f64 {
+        self.heights.total()
+    }
+
+    /// The visible line next to `line`, skipping folded lines. A
+    /// hidden `line` goes to the visible line above or below it.
+    pub fn adjacent_line(
+        &self,
+        line: usize,
+        down: bool
+    ) -> Option<usize> {
+        let line = line.min(self.lines.len());
+        let hidden = self.folded_block_of(line);
+        let line = if down {
+            let next = hidden.map(|x| x.end_line).unwrap_or(line + 1);
+            self.folded_block_of(next)
+                .map(|x| x.end_line)
+                .unwrap_or(next)
+        } else if let Some(block) = hidden {
+            block.start_line
+        } else {
+            let prev = line.checked_sub(1)?;
+            self.folded_block_of(prev)
+                .map(|x| x.start_line)
+                .unwrap_or(prev)
+        };
+        (line < self.lines.len()).then_some(line)
+    }
+
+    pub fn is_visible(&self, line: usize) -> bool {
+        line < self.lines.len()
+            && self.folded_block_of(line).is_none()
+    }
+
+    /// the lines of `range` not hidden by a folded block
+    pub fn visible_lines(&self, range: Range<usize>) -> Vec<usize> {
+        let end = range.end.min(self.lines.len());
+        let mut line = range.start;
+        let mut lines = vec![];
+        while line < end {
+            match self.folded_block_of(line) {
+                Some(block) => line = block.end_line,
+                None => {
+                    lines.push(line);
+                    line += 1;
+                }
+            }
+        }
+        lines
+    }
+
+    /// the folded block hiding `line`, its header stays visible
+    fn folded_block_of(
+        &self,
+        line: usize
+    ) -> Option<&DiagnosticBlock> {
+        let block = &self.blocks[self.block_of_line(line)?];
+        (block.folded && block.start_line < line).then_some(block)
+    }
+
+    /// index of the block that contains `line`
+    pub fn block_of_line(&self, line: usize) -> Option<usize> {
+        let index = self
+            .blocks
+            .partition_point(|x| x.start_line <= line)
+            .checked_sub(1)?;
+        self.blocks[index].contains(line).then_some(index)
+    }
+
+    /// (arrival time, level) if `line` is the first line of a block
+    pub fn block_head(
+        &self,
+        line: usize
+    ) -> Option<(DateTime<Local>, Level)> {
+        let block = &self.blocks[self.block_of_line(line)?];
+        (block.start_line == line)
+            .then_some((block.time, block.level))
+    }
+
+    /// `Some(folded)` if `line` is the header of a multi-line block
+    pub fn fold_state(&self, line: usize) -> Option<bool> {
+        let block = &self.blocks[self.block_of_line(line)?];
+        (block.start_line == line && block.is_multi_line())
+            .then_some(block.folded)
+    }
+
+    /// Fold/unfold the block whose header is `line`. Return `false`
+    /// if `line` is not the header of a multi-line block.
+    pub fn toggle_fold(&mut self, line: usize) -> bool {
+        let Some(index) = self.block_of_line(line) else {
+            return false;
+        };
+        let block = &mut self.blocks[index];
+        if block.start_line != line || !block.is_multi_line() {
+            return false;
+        }
+        block.folded = !block.folded;
+        let folded = block.folded;
+        let hidden = block.start_line + 1..block.end_line;
+        let heights: Vec<(usize, f64)> = hidden
+            .map(|x| {
+                (x, if folded { 0.0 } else { self.lines[x].height })
+            })
+            .collect();
+        self.heights.set_heights(&heights);
+        true
+    }
+
+    pub fn fold_all(&mut self, folded: bool) {
+        self.blocks.iter_mut().for_each(|x| x.folded = folded);
+        self.update_heights();
+    }
+
+    fn push_line(&mut self, line: SimpleLine) {
+        self.heights.push(line.height);
+        self.lines.push(line);
+    }
+
+    fn update_heights(&mut self) {
+        let mut heights: Vec<f64> =
+            self.lines.iter().map(|x| x.height).collect();
+        for block in self.blocks.iter().filter(|x| x.folded) {
+            let end = block.end_line.min(heights.len());
+            for line in block.start_line + 1..end {
+                heights[line] = 0.0;
+            }
+        }
+        self.heights = HeightIndex::from_heights(heights.into_iter());
+    }
+}
+
+/// The lines of one `StyledText`, e.g. a whole rustc diagnostic.
 #[derive(Clone, Debug)]
-pub struct SimpleHyperlink {
-    pub rect:       Rect,
-    pub link_index: usize
+pub struct DiagnosticBlock {
+    pub start_line: usize,
+    /// exclusive
+    pub end_line:   usize,
+    pub level:      Level,
+    /// only the header line is shown
+    pub folded:     bool,
+    /// when the `StyledText` arrived
+    pub time:       DateTime<Local>,
+    pub text_src:   TextSrc,
+    pub task:       Option<TaskId>
 }
 
-impl SimpleHyperlink {
-    pub fn underline(&self) -> (Point, Point) {
-        (
-            Point::new(self.rect.x0, self.rect.y1 - 2.0),
-            Point::new(self.rect.x1, self.rect.y1 - 2.0)
-        )
+impl DiagnosticBlock {
+    pub fn contains(&self, line: usize) -> bool {
+        self.start_line <= line && line < self.end_line
+    }
+
+    pub fn is_multi_line(&self) -> bool {
+        self.end_line - self.start_line > 1
     }
 }
 
 #[derive(Clone, Debug)]
 pub struct SimpleLine {
     pub line_index: usize,
-    pub hyperlinks: Vec<(Point, Point)>,
-    pub text_index: usize
+    /// index of `Lines::hyperlinks`
+    pub hyperlinks: Vec<usize>,
+    pub text_index: usize,
+    pub level:      Level,
+    /// visual rows of the (wrapped) line
+    pub rows:       usize,
+    /// rows and decorations
+    pub height:     f64,
+    /// `rows` were counted on the shaped text, not estimated
+    pub measured:   bool
 }
 
 impl Lines {
+    pub fn new(style: DocStyle) -> Self {
+        let mut ropes = HashMap::new();
+        ropes.insert(DisplayId::All, DisplayLines::default());
+        Self {
+            display_strategy: Default::default(),
+            ropes,
+            hyperlinks: vec![],
+            texts: vec![],
+            wrap_width: None,
+            char_width: style.char_width(),
+            style,
+            started: None,
+            grouping: Rc::new(GroupBy::default()),
+            layouts: RefCell::new(LayoutCache::new(
+                LAYOUT_CACHE_CAPACITY
+            )),
+            snippets: RefCell::new(LayoutCache::new(
+                LAYOUT_CACHE_CAPACITY
+            ))
+        }
+    }
+
+    /// Layouts are shaped with the colors of `style`, so they are
+    /// shaped again.
+    pub fn set_style(&mut self, style: DocStyle) {
+        self.char_width = style.char_width();
+        self.style = style;
+        self.layouts.get_mut().clear();
+        self.snippets.get_mut().clear();
+    }
+
+    /// Show the lines of `id`, empty if there are none yet, e.g. a
+    /// task just started.
     pub fn display(&mut self, id: DisplayId) {
+        self.ropes.entry(id.clone()).or_default();
         self.display_strategy = id;
     }
 
+    /// Drop all lines, keeping the style, wrap width and display.
+    pub fn clear(&mut self) {
+        let mut lines = Lines::new(self.style.clone());
+        lines.wrap_width = self.wrap_width;
+        lines.grouping = self.grouping.clone();
+        lines
+            .ropes
+            .entry(self.display_strategy.clone())
+            .or_default();
+        lines.display_strategy = self.display_strategy.clone();
+        *self = lines;
+    }
+
+    /// (top, line) of the visible lines in viewport
     fn display_simple_lines(
         &self,
-        viewport: Rect,
-        line_height: f64
-    ) -> Result<&[SimpleLine]> {
-        let lines = &self.line_info()?.1;
-        let len = lines.len().max(1) - 1;
-        let min_line =
-            ((viewport.y0 / line_height).floor() as usize).min(len);
-        let max_line = ((viewport.y1 / line_height).round() as usize)
-            .min(lines.len());
-        Ok(&lines[min_line..max_line])
-    }
-
-    pub fn line_info(
-        &self
-    ) -> Result<&(Rope, Vec<SimpleLine>, Vec<SimpleHyperlink>)> {
+        viewport: Rect
+    ) -> Result<Vec<(f64, &SimpleLine)>> {
+        let info = self.line_info()?;
+        let range = info.heights.range_of_y(viewport.y0, viewport.y1);
+        Ok(info
+            .visible_lines(range)
+            .into_iter()
+            .filter_map(|line| {
+                info.lines
+                    .get(line)
+                    .map(|x| (info.heights.top(line), x))
+            })
+            .collect())
+    }
+
+    pub fn line_info_mut(&mut self) -> Result<&mut DisplayLines> {
+        self.ropes
+            .get_mut(&self.display_strategy)
+            .ok_or(anyhow!("not found {:?}", self.display_strategy))
+    }
+
+    /// the top of `line`, including the decorations above its text
+    pub fn y_of_line(&self, line: usize) -> Result<f64> {
+        Ok(self.line_info()?.top_of_line(line))
+    }
+
+    /// the top of the text of `line`
+    pub fn text_y_of_line(&self, line: usize) -> Result<f64> {
+        let text = self.line_text(line)?;
+        Ok(self.y_of_line(line)? + self.space_above(text))
+    }
+
+    /// the height of one visual row of `line`
+    pub fn row_height_of_line(&self, line: usize) -> Result<f64> {
+        Ok(self.row_height(self.line_text(line)?))
+    }
+
+    /// `None` if `y` is below the last line
+    pub fn line_of_y(&self, y: f64) -> Result<Option<usize>> {
+        Ok(self.line_info()?.line_of_y(y.max(0.0)).map(|x| x.0))
+    }
+
+    pub fn line_info(&self) -> Result<&DisplayLines> {
         self.ropes
             .get(&self.display_strategy)
             .ok_or(anyhow!("not found {:?}", self.display_strategy))
     }
 
     pub fn lines_len(&self) -> Result<usize> {
-        Ok(self.line_info()?.1.len())
+        Ok(self.line_info()?.lines.len())
     }
 
     pub fn visual_lines(
         &self,
         viewport: Rect,
-        line_height: f64,
-        hyper_color: Color
+        hover_hyperlink: Option<usize>
     ) -> Result<Vec<VisualLine>> {
+        let theme = &self.style.theme;
+        let info = self.line_info()?;
         Ok(self
-            .display_simple_lines(viewport, line_height)?
-            .iter()
-            .filter_map(|x| {
-                let pos_y: f64 = x.line_index as f64 * line_height;
-
+            .display_simple_lines(viewport)?
+            .into_iter()
+            .filter_map(|(top, x)| {
+                let text = match self.layout_of_text(x.text_index) {
+                    Ok(text) => text,
+                    Err(err) => {
+                        warn!("{err:?}");
+                        return None;
+                    }
+                };
+                let line_text = self.texts.get(x.text_index)?;
+                let row_height = self.row_height(line_text);
+                let pos_y = top + self.space_above(line_text);
                 let hyperlinks = x
                     .hyperlinks
-                    .clone()
-                    .into_iter()
-                    .map(|x| (x.0, x.1, hyper_color))
-                    .collect();
-                if let Some(text) = self.texts.get(x.text_index) {
-                    Some(VisualLine {
-                        pos_y,
-                        line_index: x.line_index,
-                        hyperlinks,
-                        text: text.clone()
+                    .iter()
+                    .filter_map(|index| {
+                        let hovered = hover_hyperlink == Some(*index);
+                        Some((self.hyperlinks.get(*index)?, hovered))
                     })
-                } else {
-                    warn!("not found text layout: {}", x.text_index);
-                    None
-                }
+                    .flat_map(|(link, hovered)| {
+                        range_rects(&text, link.range(), row_height)
+                            .into_iter()
+                            .map(move |rect| (rect, hovered))
+                    })
+                    .map(|(rect, hovered)| {
+                        let y = pos_y + rect.y1 - 2.0;
+                        VisualHyperlink {
+                            start: Point::new(rect.x0, y),
+                            end: Point::new(rect.x1, y),
+                            color: if hovered {
+                                theme.hyperlink_hover
+                            } else {
+                                theme.hyperlink
+                            },
+                            hovered
+                        }
+                    })
+                    .collect();
+                let decorations = self.visual_decorations(
+                    x.text_index,
+                    top,
+                    pos_y + x.rows as f64 * row_height
+                );
+                Some(VisualLine {
+                    pos_y,
+                    row_height,
+                    line_index: x.line_index,
+                    hyperlinks,
+                    folded: info.fold_state(x.line_index),
+                    decorations,
+                    block_head: info.block_head(x.line_index),
+                    level: x.level,
+                    text
+                })
             })
             .collect())
     }
 
+    /// `top`: the top of the line, `text_bottom`: the bottom of its
+    /// last row
+    fn visual_decorations(
+        &self,
+        text_index: usize,
+        top: f64,
+        text_bottom: f64
+    ) -> Vec<VisualDecoration> {
+        let Some(text) = self.texts.get(text_index) else {
+            return vec![];
+        };
+        let mut decorations = vec![];
+        if text.decorations.contains(&Decoration::Separator) {
+            let y = top + self.space_above(text) / 2.0;
+            decorations.push(VisualDecoration::Rule(y));
+        }
+        if let Some(snippet) = text.snippet() {
+            let layouts =
+                self.layouts_of_snippet(text_index, snippet);
+            let padding = self.style.line_height / 4.0;
+            let width = layouts
+                .iter()
+                .map(|x| x.size().width)
+                .fold(0.0, f64::max);
+            let rect = Rect::new(
+                0.0,
+                text_bottom + padding,
+                width + padding * 2.0,
+                text_bottom + self.space_below(text) - padding
+            );
+            decorations
+                .push(VisualDecoration::Snippet(rect, layouts));
+        }
+        decorations
+    }
+
+    /// `text_x`: the x where text starts
     pub(crate) fn visual_lines_size(
         &self,
         viewport: Rect,
-        line_height: f64
+        text_x: f64
     ) -> Result<Size> {
         let viewport_size = viewport.size();
-        let len = self.lines_len()?;
-        let height = (len as f64 * line_height
+        let height = (self.line_info()?.height()
             + viewport.size().height / 4.0)
             .max(viewport_size.height);
 
         let max_width = self
-            .display_simple_lines(viewport, line_height)?
-            .iter()
-            .fold(0., |x, line| {
+            .display_simple_lines(viewport)?
+            .into_iter()
+            .fold(0., |x, (_, line)| {
                 match self.text_layout_of_line(line.line_index) {
                     Ok(text_layout) => {
-                        let width = text_layout.size().width;
+                        let width = text_layout.size().width + text_x;
                         if x < width { width } else { x }
                     },
                     Err(err) => {
@@ -230,18 +800,173 @@ impl Lines {
     pub fn text_layout_of_line(
         &self,
         line: usize
-    ) -> Result<&TextLayout> {
-        let line_index = self.line_info()?.1.get(line);
-        line_index
-            .and_then(|index| self.texts.get(index.text_index))
-            .ok_or(anyhow!("not found {}", line))
+    ) -> Result<Rc<TextLayout>> {
+        self.layout_of_text(self.text_index_of_line(line)?)
+    }
+
+    fn text_index_of_line(&self, line: usize) -> Result<usize> {
+        Ok(self
+            .line_info()?
+            .lines
+            .get(line)
+            .ok_or(anyhow!("not found {}", line))?
+            .text_index)
+    }
+
+    fn line_text(&self, line: usize) -> Result<&LineText> {
+        let text_index = self.text_index_of_line(line)?;
+        self.texts
+            .get(text_index)
+            .ok_or(anyhow!("not found text {}", text_index))
+    }
+
+    /// The shaped layout of `texts[text_index]`, shaping it on a
+    /// cache miss.
+    fn layout_of_text(
+        &self,
+        text_index: usize
+    ) -> Result<Rc<TextLayout>> {
+        let cached = self.layouts.borrow_mut().get(&text_index);
+        if let Some(layout) = cached {
+            return Ok(layout);
+        }
+        let text = self
+            .texts
+            .get(text_index)
+            .ok_or(anyhow!("not found text {}", text_index))?;
+        let layout = Rc::new(self.shape(text));
+        self.layouts.borrow_mut().insert(text_index, layout.clone());
+        Ok(layout)
+    }
+
+    fn shape(&self, text: &LineText) -> TextLayout {
+        let family = Cow::Owned(
+            FamilyOwned::parse_list(&self.style.font_family)
+                .collect()
+        );
+        let scale = text.scale();
+        let mut attrs_list =
+            AttrsList::new(self.style.scaled_attrs(&family, scale));
+        text.styles.iter().cloned().for_each(|x| {
+            to_line_attrs(
+                &mut attrs_list,
+                self.style.scaled_attrs(&family, scale),
+                x
+            )
+        });
+        let mut font_system = FONT_SYSTEM.lock();
+        TextLayout::new_with_config(
+            0,
+            &text.content,
+            attrs_list,
+            &mut font_system,
+            self.wrap_width,
+            Wrap::WordOrGlyph
+        )
     }
 
+    /// one unwrapped layout per line of the snippet
+    fn layouts_of_snippet(
+        &self,
+        text_index: usize,
+        snippet: &str
+    ) -> Rc<Vec<TextLayout>> {
+        let cached = self.snippets.borrow_mut().get(&text_index);
+        if let Some(layouts) = cached {
+            return layouts;
+        }
+        let family = Cow::Owned(
+            FamilyOwned::parse_list(&self.style.font_family)
+                .collect()
+        );
+        let layouts = {
+            let mut font_system = FONT_SYSTEM.lock();
+            Rc::new(
+                snippet
+                    .lines()
+                    .map(|line| {
+                        TextLayout::new_with_config(
+                            0,
+                            line,
+                            AttrsList::new(self.style.attrs(&family)),
+                            &mut font_system,
+                            None,
+                            Wrap::None
+                        )
+                    })
+                    .collect::<Vec<_>>()
+            )
+        };
+        self.snippets
+            .borrow_mut()
+            .insert(text_index, layouts.clone());
+        layouts
+    }
+
+    /// Rows taken by `texts[text_index]`. Without soft wrap every
+    /// line is a single row and nothing needs to be shaped.
+    fn rows_of_text(&self, text_index: usize) -> usize {
+        if self.wrap_width.is_none() {
+            return 1;
+        }
+        match self.layout_of_text(text_index) {
+            Ok(text) => rows_of_text(&text),
+            Err(err) => {
+                error!("{err:?}");
+                1
+            }
+        }
+    }
+
+    /// Rows of `text` guessed from its width in monospace cells,
+    /// until it is shaped. Word wrap may take a few more.
+    fn estimate_rows(&self, text: &LineText) -> usize {
+        let Some(wrap_width) = self.wrap_width else {
+            return 1;
+        };
+        let width = text.content.chars().count() as f64
+            * self.char_width
+            * text.scale() as f64;
+        ((width / wrap_width as f64).ceil() as usize).max(1)
+    }
+
+    fn row_height(&self, text: &LineText) -> f64 {
+        self.style.line_height * text.scale() as f64
+    }
+
+    /// room for a separator rule above the text
+    fn space_above(&self, text: &LineText) -> f64 {
+        if text.decorations.contains(&Decoration::Separator) {
+            self.style.line_height / 2.0
+        } else {
+            0.0
+        }
+    }
+
+    /// room for a snippet below the text, with half a line of
+    /// padding around it
+    fn space_below(&self, text: &LineText) -> f64 {
+        text.snippet()
+            .map(|snippet| {
+                (snippet.lines().count().max(1) + 1) as f64
+                    * self.style.line_height
+            })
+            .unwrap_or_default()
+    }
+
+    fn height_of_text(&self, text: &LineText, rows: usize) -> f64 {
+        self.space_above(text)
+            + rows as f64 * self.row_height(text)
+            + self.space_below(text)
+    }
+
+    /// return (point, line, offset_of_line), the y of point is
+    /// relative to the top of the line
     pub fn point_of_offset(
         &self,
         offset: usize
     ) -> Result<Option<(Point, usize, usize)>> {
-        let rope = &self.line_info()?.0;
+        let rope = &self.line_info()?.rope;
         if rope.is_empty() {
             return Ok(None);
         }
@@ -249,24 +974,70 @@ impl Lines {
         let line = rope.line_of_offset(offset);
         let offset_line = rope.offset_of_line(line)?;
         let text = self.text_layout_of_line(line)?;
-        let point =
-            hit_position_aff(text, offset - offset_line, true).point;
+        let line_text = self.line_text(line)?;
+        let hit = hit_position_aff(&text, offset - offset_line, true);
+        let point = Point::new(
+            hit.point.x,
+            self.space_above(line_text)
+                + hit.line as f64 * self.row_height(line_text)
+        );
         Ok(Some((point, line, offset_line)))
     }
 
+    /// The y in the middle of the visual row above/below the row of
+    /// `offset`, skipping folded lines and decorations. `None` at the
+    /// first/last row.
+    pub fn y_of_adjacent_row(
+        &self,
+        offset: usize,
+        down: bool
+    ) -> Result<Option<f64>> {
+        let info = self.line_info()?;
+        let rope = &info.rope;
+        if rope.is_empty() {
+            return Ok(None);
+        }
+        let offset = offset.min(rope.len() - 1);
+        let line = rope.line_of_offset(offset);
+        let offset_line = rope.offset_of_line(line)?;
+        let text = self.text_layout_of_line(line)?;
+        let row =
+            hit_position_aff(&text, offset - offset_line, true).line;
+        let rows_of = |line: usize| {
+            info.lines.get(line).map(|x| x.rows).unwrap_or(1)
+        };
+        let (line, row) = if down && row + 1 < rows_of(line) {
+            (line, row + 1)
+        } else if !down && row > 0 {
+            (line, row - 1)
+        } else {
+            let Some(line) = info.adjacent_line(line, down) else {
+                return Ok(None);
+            };
+            (line, if down { 0 } else { rows_of(line) - 1 })
+        };
+        Ok(Some(
+            self.text_y_of_line(line)?
+                + (row as f64 + 0.5)
+                    * self.row_height_of_line(line)?
+        ))
+    }
+
     #[allow(clippy::too_many_arguments)]
     fn push_src(
         &mut self,
         text_src: &DisplayId,
         content_origin_without_lf: &String,
         text_index: usize,
-        hyperlink: &[Hyperlink],
+        hyperlinks: Vec<usize>,
         line_ending: LineEnding,
-        text: &TextLayout,
-        line_height: f64
+        rows: usize,
+        height: f64,
+        measured: bool,
+        level: Level
     ) {
-        let (rope, lines, links) =
-            self.ropes.entry(text_src.clone()).or_default();
+        let info = self.ropes.entry(text_src.clone()).or_default();
+        let rope = &mut info.rope;
         let mut old_len = rope.len();
         let line_index = if old_len > 0 {
             rope.line_of_offset(old_len)
@@ -278,63 +1049,41 @@ impl Lines {
             old_len += content_origin_without_lf.len();
             rope.edit(old_len..old_len, line_ending.get_chars());
         }
-
-        let start = links.len();
-        let (underlines, mut simple_link): (
-            Vec<(Point, Point)>,
-            Vec<SimpleHyperlink>
-        ) = hyperlink
-            .iter()
-            .map(|x| {
-                let range = x.range();
-                let x0 = text.hit_position(range.start).point.x;
-                let x1 = text.hit_position(range.end).point.x;
-                let y0 = line_index as f64 * line_height;
-                let y1 = (line_index + 1) as f64 * line_height;
-                let under_line_y =
-                    (line_index + 1) as f64 * line_height - 2.0;
-                (
-                    Point::new(x0, under_line_y),
-                    Point::new(x1, under_line_y),
-                    Rect::new(x0, y0, x1, y1)
-                )
-            })
-            .enumerate()
-            .fold(
-                (
-                    Vec::with_capacity(hyperlink.len()),
-                    Vec::with_capacity(hyperlink.len())
-                ),
-                |(mut underlines, mut simple_link), (index, x)| {
-                    underlines.push((x.0, x.1));
-
-                    simple_link.push(SimpleHyperlink {
-                        rect:       x.2,
-                        link_index: start + index
-                    });
-                    (underlines, simple_link)
-                }
-            );
-        let _line = SimpleLine {
+        info.push_line(SimpleLine {
             line_index,
             text_index,
-            hyperlinks: underlines
-        };
-        links.append(&mut simple_link);
-        lines.push(_line);
+            hyperlinks,
+            level,
+            rows,
+            height,
+            measured
+        });
     }
 
     pub fn in_hyperlink_region(
         &self,
         position: Point
     ) -> Result<Option<usize>> {
-        let links = &self.line_info()?.2;
-        Ok(links.iter().find_map(|x| {
-            if x.rect.contains(position) {
-                Some(x.link_index)
-            } else {
-                None
-            }
+        let info = self.line_info()?;
+        let Some(line) = self.line_of_y(position.y)? else {
+            return Ok(None);
+        };
+        let position = Point::new(
+            position.x,
+            position.y - self.text_y_of_line(line)?
+        );
+        let row_height = self.row_height_of_line(line)?;
+        let (Some(simple_line), Ok(text)) =
+            (info.lines.get(line), self.text_layout_of_line(line))
+        else {
+            return Ok(None);
+        };
+        Ok(simple_line.hyperlinks.iter().copied().find(|index| {
+            self.hyperlinks.get(*index).is_some_and(|link| {
+                range_rects(&text, link.range(), row_height)
+                    .iter()
+                    .any(|rect| rect.contains(position))
+            })
         }))
     }
 
@@ -351,80 +1100,353 @@ impl Lines {
         }))
     }
 
+    /// Re-wrap all text at `width`. `None` disables wrapping.
+    ///
+    /// Nothing is shaped here, resizing calls it at every step: the
+    /// rows are estimated again and `measure_rows` corrects the
+    /// visible ones.
+    pub fn set_wrap_width(&mut self, width: Option<f32>) {
+        if self.wrap_width == width {
+            return;
+        }
+        self.wrap_width = width;
+        self.layouts.get_mut().clear();
+        let measured = width.is_none();
+        let metrics: Vec<(usize, f64)> = self
+            .texts
+            .iter()
+            .map(|text| {
+                let rows = self.estimate_rows(text);
+                (rows, self.height_of_text(text, rows))
+            })
+            .collect();
+        for info in self.ropes.values_mut() {
+            for line in info.lines.iter_mut() {
+                if let Some((rows, height)) =
+                    metrics.get(line.text_index)
+                {
+                    line.rows = *rows;
+                    line.height = *height;
+                    line.measured = measured;
+                }
+            }
+            info.update_heights();
+        }
+    }
+
+    /// Shape the displayed lines in `viewport` whose rows are only
+    /// estimated and correct their heights. The lines below move
+    /// with a correction, so this repeats until every line in the
+    /// viewport is measured. Lines above keep their estimates, the
+    /// viewport does not jump.
+    pub fn measure_rows(&mut self, viewport: Rect) -> Result<()> {
+        if self.wrap_width.is_none() {
+            return Ok(());
+        }
+        loop {
+            let info = self.line_info()?;
+            let range =
+                info.heights.range_of_y(viewport.y0, viewport.y1);
+            let estimated: Vec<usize> = info
+                .visible_lines(range)
+                .into_iter()
+                .filter(|line| !info.lines[*line].measured)
+                .collect();
+            if estimated.is_empty() {
+                return Ok(());
+            }
+            let mut heights = Vec::with_capacity(estimated.len());
+            for line in estimated {
+                let text_index = self.text_index_of_line(line)?;
+                let rows = self.rows_of_text(text_index);
+                let text = &self.texts[text_index];
+                let height = self.height_of_text(text, rows);
+                let simple_line =
+                    &mut self.line_info_mut()?.lines[line];
+                simple_line.rows = rows;
+                simple_line.height = height;
+                simple_line.measured = true;
+                heights.push((line, height));
+            }
+            self.line_info_mut()?.heights.set_heights(&heights);
+        }
+    }
+
     pub fn append_lines(
         &mut self,
         style_lines: StyledLines,
-        line_ending: LineEnding,
-        doc_style: &DocStyle
+        line_ending: LineEnding
     ) -> anyhow::Result<()> {
-        // 新内容如果没有\n则会导致二者相等
-        let family = Cow::Owned(
-            FamilyOwned::parse_list(&doc_style.font_family).collect()
-        );
-        let display_ids = style_lines.text_src.display_ids();
-        for (content_origin_without_lf, style, mut hyperlink) in
-            style_lines.lines.into_iter()
-        {
-            let mut attrs_list =
-                AttrsList::new(doc_style.attrs(&family));
-            style.into_iter().for_each(|x| {
-                to_line_attrs(
-                    &mut attrs_list,
-                    doc_style.attrs(&family),
-                    x
-                )
-            });
-            let mut font_system = FONT_SYSTEM.lock();
-            let text = TextLayout::new_with_font_system(
-                0,
-                &content_origin_without_lf,
-                attrs_list,
-                &mut font_system
-            );
-
+        let StyledLines {
+            text_src,
+            level,
+            lines,
+            decorations,
+            task
+        } = style_lines;
+        let display_ids =
+            self.display_ids(&text_src, level, task.as_ref());
+        let time = Local::now();
+        self.started.get_or_insert(time);
+        let start_lines: Vec<usize> = display_ids
+            .iter()
+            .map(|id| {
+                self.ropes.get(id).map(|x| x.lines.len()).unwrap_or(0)
+            })
+            .collect();
+        let last = lines.len().max(1) - 1;
+        for (index, line) in lines.into_iter().enumerate() {
+            let (content_origin_without_lf, styles, mut hyperlink) =
+                line;
             let text_index = self.texts.len();
+            let link_start = self.hyperlinks.len();
+            let link_indexes: Vec<usize> =
+                (link_start..link_start + hyperlink.len()).collect();
+            let text = LineText {
+                content: content_origin_without_lf,
+                styles,
+                decorations: decorations
+                    .iter()
+                    .filter(|x| {
+                        if x.is_below() {
+                            index == last
+                        } else {
+                            index == 0
+                        }
+                    })
+                    .cloned()
+                    .collect()
+            };
+            // nothing is shaped until visible, the rows of wrapped
+            // lines are estimated until `measure_rows`
+            let rows = self.estimate_rows(&text);
+            let measured = self.wrap_width.is_none();
+            let height = self.height_of_text(&text, rows);
             for id in &display_ids {
                 self.push_src(
                     id,
-                    &content_origin_without_lf,
+                    &text.content,
                     text_index,
-                    &hyperlink,
+                    link_indexes.clone(),
                     line_ending,
-                    &text,
-                    doc_style.line_height
+                    rows,
+                    height,
+                    measured,
+                    level
                 );
             }
             self.texts.push(text);
             self.hyperlinks.append(&mut hyperlink);
-            // let hyperlinks: Vec<(Point, Point, Color)> = vec![];
-            // let hyperlinks: Vec<(Point, Point, Color)> = points
-            //     .iter()
-            //     .map(|(x0, x1, _link)| {
-            //         (
-            //             Point::new(*x0, y + doc_style.line_height -
-            // 1.0),             Point::new(*x1, y +
-            // doc_style.line_height - 1.0),
-            // doc_style.fg_color         )
-            //     })
-            //     .collect();
-            // let hyperlink_regions: Vec<(Rect, Hyperlink)> = points
-            //     .into_iter()
-            //     .map(|(x0, x1, data)| {
-            //         (
-            //             Rect::new(
-            //                 x0,
-            //                 y,
-            //                 x1,
-            //                 y + doc_style.line_height,
-            //             ),
-            //             data
-            //         )
-            //     })
-            //     .collect();
-            // line_index += 1;
+        }
+        for (id, start_line) in display_ids.iter().zip(start_lines) {
+            if let Some(info) = self.ropes.get_mut(id) {
+                if info.lines.len() > start_line {
+                    info.blocks.push(DiagnosticBlock {
+                        start_line,
+                        end_line: info.lines.len(),
+                        level,
+                        folded: false,
+                        time,
+                        text_src: text_src.clone(),
+                        task: task.clone()
+                    });
+                }
+            }
         }
         Ok(())
     }
+
+    /// The node of a `StyledText` in the tree, see `Grouping`.
+    pub fn path(&self, src: &TextSrc, level: Level) -> Vec<Segment> {
+        self.grouping.path(src, level)
+    }
+
+    /// `All` and the groups of the text, in the namespace of `task`
+    /// if any: all tasks are in `All`, the rest is per task.
+    fn display_ids(
+        &self,
+        src: &TextSrc,
+        level: Level,
+        task: Option<&TaskId>
+    ) -> Vec<DisplayId> {
+        let path = DisplayId::ancestors(&self.path(src, level));
+        match task {
+            Some(task) => std::iter::once(DisplayId::All)
+                .chain(
+                    std::iter::once(DisplayId::All)
+                        .chain(path)
+                        .map(|x| x.in_task(task))
+                )
+                .collect(),
+            None => {
+                std::iter::once(DisplayId::All).chain(path).collect()
+            },
+        }
+    }
+
+    /// Group the lines with `grouping` from now on, regrouping the
+    /// lines so far. `DisplayId::All` keeps its lines and folds.
+    pub fn set_grouping(
+        &mut self,
+        grouping: Rc<dyn Grouping>,
+        line_ending: LineEnding
+    ) {
+        self.grouping = grouping;
+        let all =
+            self.ropes.remove(&DisplayId::All).unwrap_or_default();
+        self.ropes.clear();
+        for block in &all.blocks {
+            let ids = self.display_ids(
+                &block.text_src,
+                block.level,
+                block.task.as_ref()
+            );
+            for id in ids.iter().filter(|x| **x != DisplayId::All) {
+                let start_line = self
+                    .ropes
+                    .get(id)
+                    .map(|x| x.lines.len())
+                    .unwrap_or(0);
+                let range = block.start_line..block.end_line;
+                for line in &all.lines[range] {
+                    let content =
+                        self.texts[line.text_index].content.clone();
+                    self.push_src(
+                        id,
+                        &content,
+                        line.text_index,
+                        line.hyperlinks.clone(),
+                        line_ending,
+                        line.rows,
+                        line.height,
+                        line.measured,
+                        line.level
+                    );
+                }
+                if let Some(info) = self.ropes.get_mut(id) {
+                    info.blocks.push(DiagnosticBlock {
+                        start_line,
+                        end_line: info.lines.len(),
+                        folded: false,
+                        ..block.clone()
+                    });
+                }
+            }
+        }
+        self.ropes.insert(DisplayId::All, all);
+    }
+
+    /// the texts appended so far, in order
+    pub fn blocks(&self) -> &[DiagnosticBlock] {
+        self.ropes
+            .get(&DisplayId::All)
+            .map(|x| x.blocks.as_slice())
+            .unwrap_or_default()
+    }
+
+    /// The text of `range` in the current display. Ansi is built line
+    /// by line from the styles kept for each line.
+    pub fn export(
+        &self,
+        range: Range<usize>,
+        format: CopyFormat
+    ) -> Result<String> {
+        let info = self.line_info()?;
+        let rope = &info.rope;
+        let end = range.end.min(rope.len());
+        let start = range.start.min(end);
+        if format == CopyFormat::PlainText {
+            return Ok(rope.slice_to_cow(start..end).to_string());
+        }
+        let last = rope
+            .line_of_offset(end)
+            .min(info.lines.len().saturating_sub(1));
+        let mut lines = vec![];
+        for line in rope.line_of_offset(start)..=last {
+            let line_start = rope.offset_of_line(line)?;
+            let text = self.line_text(line)?;
+            let range = start.saturating_sub(line_start)
+                ..end.saturating_sub(line_start);
+            lines.push(to_ansi(&text.content, &text.styles, range));
+        }
+        Ok(lines.join("\n"))
+    }
+
+    /// The first line of the next/previous block with `level`,
+    /// starting from `line` and wrapping around. `None` starts from
+    /// the top (or the bottom when going backward).
+    pub fn diagnostic_line(
+        &self,
+        line: Option<usize>,
+        level: Level,
+        forward: bool
+    ) -> Result<Option<usize>> {
+        let mut blocks = self
+            .line_info()?
+            .blocks
+            .iter()
+            .filter(|x| x.level == level);
+        let block = if forward {
+            blocks
+                .clone()
+                .find(|x| line.is_none_or(|line| x.start_line > line))
+                .or_else(|| blocks.next())
+        } else {
+            blocks
+                .clone()
+                .rev()
+                .find(|x| line.is_none_or(|line| x.start_line < line))
+                .or_else(|| blocks.next_back())
+        };
+        Ok(block.map(|x| x.start_line))
+    }
+}
+
+fn rows_of_text(text: &TextLayout) -> usize {
+    text.layout_runs().count().max(1)
+}
+
+/// The rects (relative to the top of the line) covered by `range`,
+/// one for every visual row it spans.
+fn range_rects(
+    text: &TextLayout,
+    range: Range<usize>,
+    line_height: f64
+) -> Vec<Rect> {
+    let start = hit_position_aff(text, range.start, false);
+    let end = hit_position_aff(text, range.end, true);
+    let top = |row: usize| row as f64 * line_height;
+    if start.line >= end.line {
+        return vec![Rect::new(
+            start.point.x,
+            top(start.line),
+            end.point.x,
+            top(start.line) + line_height
+        )];
+    }
+    let width = text.size().width;
+    let mut rects = Vec::with_capacity(end.line - start.line + 1);
+    rects.push(Rect::new(
+        start.point.x,
+        top(start.line),
+        width,
+        top(start.line) + line_height
+    ));
+    for row in start.line + 1..end.line {
+        rects.push(Rect::new(
+            0.0,
+            top(row),
+            width,
+            top(row) + line_height
+        ));
+    }
+    rects.push(Rect::new(
+        0.0,
+        top(end.line),
+        end.point.x,
+        top(end.line) + line_height
+    ));
+    rects
 }
 
 fn to_line_attrs(
@@ -461,65 +1483,102 @@ pub enum TextSrc {
     },
     StdErr {
         level: ErrLevel
+    },
+    /// a line of a `ProcessRunner`, grouped under `tag` in the tree,
+    /// `suite::test` nesting `test` under `suite`
+    Process {
+        name: String,
+        tag:  Option<String>
     }
 }
 
 impl TextSrc {
-    pub fn display_id(&self) -> DisplayId {
+    pub fn crate_name(&self) -> Option<&str> {
         match self {
-            TextSrc::StdOut {
-                crate_name, file, ..
-            } => {
-                if let Some(file) = file {
-                    DisplayId::CrateFile {
-                        crate_name: crate_name.clone(),
-                        file_name:  file.clone()
-                    }
-                } else {
-                    DisplayId::Crate {
-                        crate_name: crate_name.clone()
-                    }
-                }
+            TextSrc::StdOut { crate_name, .. } => Some(crate_name),
+            TextSrc::StdErr { .. } | TextSrc::Process { .. } => None
+        }
+    }
+
+    /// The cargo package of the crate, for `cargo -p`: `crate_name`
+    /// is the name of the target, e.g. `cozy_floem` for the package
+    /// `cozy-floem`.
+    pub fn package_name(&self) -> Option<&str> {
+        let TextSrc::StdOut { package_id, .. } = self else {
+            return None;
+        };
+        let repr = package_id.repr.as_str();
+        match repr.rsplit_once('#') {
+            // `path+file:///dir/cozy-floem#0.1.0`,
+            // `registry+https://...#serde@1.0.217`
+            Some((url, fragment)) => match fragment.split_once('@') {
+                Some((name, _version)) => Some(name),
+                None => url.trim_end_matches('/').rsplit('/').next()
             },
-            TextSrc::StdErr { level } => match level {
-                ErrLevel::Error => DisplayId::Error,
-                ErrLevel::Other => DisplayId::All
-            }
+            // `cozy-floem 0.1.0 (path+file:///dir/cozy-floem)`
+            None => repr.split_whitespace().next()
         }
     }
 
-    pub fn display_ids(&self) -> Vec<DisplayId> {
-        match self {
-            TextSrc::StdOut {
-                crate_name, file, ..
-            } => {
-                if let Some(file) = file {
-                    vec![
-                        DisplayId::All,
-                        DisplayId::Crate {
-                            crate_name: crate_name.clone()
-                        },
-                        DisplayId::CrateFile {
-                            crate_name: crate_name.clone(),
-                            file_name:  file.clone()
-                        },
-                    ]
-                } else {
-                    vec![DisplayId::All, DisplayId::Crate {
-                        crate_name: crate_name.clone()
-                    }]
-                }
+    /// The `Cargo.toml` of a path package, e.g. a workspace member,
+    /// `None` for registry and git packages.
+    pub fn manifest_path(&self) -> Option<PathBuf> {
+        let TextSrc::StdOut { package_id, .. } = self else {
+            return None;
+        };
+        let repr = package_id.repr.as_str();
+        let url = match repr.split_once(" (") {
+            Some((_, url)) => url.trim_end_matches(')'),
+            None => repr.split('#').next()?
+        };
+        let path = url.strip_prefix("path+file://")?;
+        // `/D:/git/app` on windows
+        let drive = path.as_bytes().get(1..3).is_some_and(|x| {
+            x[0].is_ascii_alphabetic() && x[1] == b':'
+        });
+        let path = if drive { &path[1..] } else { path };
+        Some(PathBuf::from(percent_decode(path)).join("Cargo.toml"))
+    }
+
+    /// the path package of the crate, see `manifest_path`
+    pub fn package(&self) -> Option<CargoPackage> {
+        Some(CargoPackage {
+            name:          self.package_name()?.to_string(),
+            manifest_path: self.manifest_path()?
+        })
+    }
+}
+
+/// A path package of the output, what `cargo -p` needs to build it
+/// in its own workspace.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CargoPackage {
+    pub name:          String,
+    pub manifest_path: PathBuf
+}
+
+/// `%20` and the like of a file url
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut rs = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        let escaped = bytes
+            .get(index + 1..index + 3)
+            .and_then(|x| std::str::from_utf8(x).ok())
+            .and_then(|x| u8::from_str_radix(x, 16).ok());
+        match (bytes[index], escaped) {
+            (b'%', Some(byte)) => {
+                rs.push(byte);
+                index += 3;
             },
-            TextSrc::StdErr { level } => match level {
-                ErrLevel::Error => {
-                    vec![DisplayId::All, DisplayId::Error]
-                },
-                ErrLevel::Other => {
-                    vec![DisplayId::All]
-                }
+            (byte, _) => {
+                rs.push(byte);
+                index += 1;
             }
         }
     }
+    String::from_utf8_lossy(&rs).into_owned()
 }
 
 #[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
@@ -527,3 +1586,255 @@ pub enum ErrLevel {
     Error,
     Other
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn process(tag: &str) -> TextSrc {
+        TextSrc::Process {
+            name: "test".to_string(),
+            tag:  Some(tag.to_string())
+        }
+    }
+
+    /// one block of `count` lines per `(tag, level, count)`, each
+    /// line one row high
+    fn lines(blocks: &[(&str, Level, usize)]) -> Lines {
+        let mut lines = Lines::default();
+        for (tag, level, count) in blocks {
+            let styled_lines = StyledLines {
+                text_src:    process(tag),
+                level:       *level,
+                lines:       (0..*count)
+                    .map(|x| (format!("{tag} {x}"), vec![], vec![]))
+                    .collect(),
+                decorations: vec![],
+                task:        None
+            };
+            lines.append_lines(styled_lines, LineEnding::Lf).unwrap();
+        }
+        lines
+    }
+
+    #[test]
+    fn test_diagnostic_line() {
+        let lines = lines(&[
+            ("a", Level::Warn, 2),
+            ("b", Level::Error, 1),
+            ("c", Level::Warn, 3),
+            ("d", Level::Error, 2)
+        ]);
+        let next = |line, level, forward| {
+            lines.diagnostic_line(line, level, forward).unwrap()
+        };
+        assert_eq!(Some(2), next(None, Level::Error, true));
+        assert_eq!(Some(6), next(None, Level::Error, false));
+        assert_eq!(Some(6), next(Some(2), Level::Error, true));
+        assert_eq!(Some(6), next(Some(4), Level::Error, true));
+        // wraps around
+        assert_eq!(Some(2), next(Some(6), Level::Error, true));
+        assert_eq!(Some(3), next(Some(4), Level::Warn, false));
+        assert_eq!(Some(0), next(Some(3), Level::Warn, false));
+        assert_eq!(Some(3), next(Some(0), Level::Warn, false));
+        assert_eq!(None, next(None, Level::Note, true));
+    }
+
+    #[test]
+    fn test_fold() {
+        let mut lines = lines(&[
+            ("a", Level::Warn, 3),
+            ("b", Level::Error, 1),
+            ("c", Level::Warn, 2)
+        ]);
+        let info = lines.line_info_mut().unwrap();
+        assert!(info.toggle_fold(0));
+        // not a header, a single line
+        assert!(!info.toggle_fold(1));
+        assert!(!info.toggle_fold(3));
+
+        assert_eq!(vec![0, 3, 4, 5], info.visible_lines(0..6));
+        assert_eq!(vec![3, 4], info.visible_lines(1..5));
+        assert_eq!(vec![5], info.visible_lines(5..10));
+        assert!(info.is_visible(0));
+        assert!(!info.is_visible(2));
+        assert!(!info.is_visible(6));
+        assert_eq!(4.0 * 23.0, info.height());
+        // a hidden line is at its header
+        assert_eq!(0.0, info.top_of_line(2));
+        assert_eq!(23.0, info.top_of_line(3));
+        assert_eq!(Some((0, 0.0)), info.line_of_y(22.0));
+        assert_eq!(Some((3, 23.0)), info.line_of_y(23.0));
+
+        assert!(info.toggle_fold(0));
+        assert_eq!(vec![0, 1, 2, 3], info.visible_lines(0..4));
+        assert_eq!(6.0 * 23.0, info.height());
+        assert_eq!(46.0, info.top_of_line(2));
+
+        info.fold_all(true);
+        assert_eq!(vec![0, 3, 4], info.visible_lines(0..6));
+        assert_eq!(3.0 * 23.0, info.height());
+        info.fold_all(false);
+        assert_eq!(6.0 * 23.0, info.height());
+    }
+
+    #[test]
+    fn test_adjacent_line() {
+        let mut lines = lines(&[
+            ("a", Level::Warn, 3),
+            ("b", Level::Error, 1),
+            ("c", Level::Warn, 2)
+        ]);
+        let info = lines.line_info_mut().unwrap();
+        assert_eq!(Some(1), info.adjacent_line(0, true));
+        assert_eq!(None, info.adjacent_line(0, false));
+        assert_eq!(None, info.adjacent_line(5, true));
+
+        info.toggle_fold(0);
+        assert_eq!(Some(3), info.adjacent_line(0, true));
+        assert_eq!(Some(0), info.adjacent_line(3, false));
+        // a hidden line goes to the visible line below or above it
+        assert_eq!(Some(3), info.adjacent_line(1, true));
+        assert_eq!(Some(0), info.adjacent_line(2, false));
+
+        info.toggle_fold(4);
+        assert_eq!(Some(4), info.adjacent_line(3, true));
+        assert_eq!(None, info.adjacent_line(4, true));
+        assert_eq!(None, info.adjacent_line(5, true));
+        assert_eq!(Some(3), info.adjacent_line(4, false));
+        // past the end goes to the last visible line
+        assert_eq!(Some(4), info.adjacent_line(9, false));
+    }
+
+    #[test]
+    fn test_set_grouping() {
+        let mut lines = lines(&[
+            ("a::x", Level::Warn, 2),
+            ("b", Level::Error, 1),
+            ("a::y", Level::Warn, 3)
+        ]);
+        let tag_a = DisplayId::Path(vec![Segment::Tag("a".into())]);
+        assert_eq!(5, lines.ropes[&tag_a].lines.len());
+        lines.line_info_mut().unwrap().toggle_fold(0);
+
+        lines.set_grouping(
+            Rc::new(GroupBy::SeverityCrate),
+            LineEnding::Lf
+        );
+        let level = |x| DisplayId::Path(vec![Segment::Level(x)]);
+        let mut ids: Vec<&DisplayId> = lines.ropes.keys().collect();
+        ids.sort();
+        let (warn, error) = (level(Level::Warn), level(Level::Error));
+        assert_eq!(vec![&DisplayId::All, &warn, &error], ids);
+        let warnings = &lines.ropes[&warn];
+        assert_eq!(5, warnings.lines.len());
+        assert_eq!(
+            vec![(0, 2), (2, 5)],
+            warnings
+                .blocks
+                .iter()
+                .map(|x| (x.start_line, x.end_line))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(5.0 * 23.0, warnings.height());
+        // all lines keep their folds
+        let all = lines.line_info().unwrap();
+        assert_eq!(6, all.lines.len());
+        assert_eq!(Some(true), all.fold_state(0));
+
+        // new lines go to the new groups
+        lines
+            .append_lines(
+                StyledLines {
+                    text_src:    process("c"),
+                    level:       Level::Error,
+                    lines:       vec![("c 0".into(), vec![], vec![])],
+                    decorations: vec![],
+                    task:        None
+                },
+                LineEnding::Lf
+            )
+            .unwrap();
+        assert_eq!(2, lines.ropes[&error].lines.len());
+    }
+
+    const PATH_ID: &str = "path+file:///dir/cozy-floem#0.1.0";
+    const OLD_PATH_ID: &str =
+        "cozy-floem 0.1.0 (path+file:///dir/cozy-floem)";
+    const REGISTRY_ID: &str =
+        "registry+https://github.com/rust-lang/crates.io#serde@1.0.2";
+
+    fn stdout(repr: &str) -> TextSrc {
+        TextSrc::StdOut {
+            package_id: PackageId {
+                repr: repr.to_string()
+            },
+            crate_name: "cozy_floem".to_string(),
+            file:       None
+        }
+    }
+
+    #[test]
+    fn test_package_name() {
+        let name = |repr: &str| {
+            stdout(repr).package_name().map(str::to_string)
+        };
+        let cozy = Some("cozy-floem".to_string());
+        assert_eq!(cozy, name(PATH_ID));
+        assert_eq!(cozy, name(OLD_PATH_ID));
+        // a package named unlike its directory
+        let renamed = "path+file:///dir/app#cozy-floem@0.1.0";
+        assert_eq!(cozy, name(renamed));
+        assert_eq!(Some("serde".to_string()), name(REGISTRY_ID));
+        assert_eq!(None, process("a").package_name());
+    }
+
+    #[test]
+    fn test_manifest_path() {
+        let path = |repr: &str| stdout(repr).manifest_path();
+        let manifest = PathBuf::from("/dir/cozy-floem/Cargo.toml");
+        assert_eq!(Some(manifest.clone()), path(PATH_ID));
+        assert_eq!(Some(manifest.clone()), path(OLD_PATH_ID));
+        assert_eq!(
+            Some(PathBuf::from("D:/git/my app/Cargo.toml")),
+            path("path+file:///D:/git/my%20app#0.1.0")
+        );
+        assert_eq!(None, path(REGISTRY_ID));
+
+        let package = CargoPackage {
+            name:          "cozy-floem".to_string(),
+            manifest_path: manifest
+        };
+        assert_eq!(Some(package), stdout(PATH_ID).package());
+        assert_eq!(None, stdout(REGISTRY_ID).package());
+        assert_eq!(None, process("a").package());
+    }
+
+    #[test]
+    fn test_display_id_contains() {
+        let path = |names: &[&str]| {
+            let tag = |x: &&str| Segment::Tag(x.to_string());
+            DisplayId::Path(names.iter().map(tag).collect())
+        };
+        let task = |index: usize| TaskId {
+            index,
+            name: "test".to_string()
+        };
+        let (a, ab) = (path(&["a"]), path(&["a", "b"]));
+        assert!(DisplayId::All.contains(&a));
+        let a_in_task = a.clone().in_task(&task(0));
+        assert!(DisplayId::All.contains(&a_in_task));
+        assert!(a.contains(&a));
+        assert!(a.contains(&ab));
+        assert!(!ab.contains(&a));
+        assert!(!a.contains(&path(&["b", "a"])));
+        assert!(!a.contains(&DisplayId::All));
+
+        let ab_in_task = ab.in_task(&task(0));
+        let all_of_task = DisplayId::All.in_task(&task(0));
+        assert!(all_of_task.contains(&ab_in_task));
+        assert!(a_in_task.contains(&ab_in_task));
+        assert!(!a.clone().in_task(&task(1)).contains(&ab_in_task));
+        assert!(!a.contains(&ab_in_task));
+    }
+}
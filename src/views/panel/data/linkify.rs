@@ -0,0 +1,347 @@
+use crate::views::{
+    panel::Hyperlink, tree_with_panel::data::StyledText
+};
+use log::error;
+use regex::{Captures, Regex};
+use std::ops::Range;
+
+/// What the matches of a `LinkPattern` link to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LinkKind {
+    /// needs the named groups `path` and `line`, `column` is optional
+    File,
+    Url
+}
+
+/// A regex whose matches become hyperlinks. The group `link`, if
+/// present, is the clickable range, otherwise the whole match.
+#[derive(Clone, Debug)]
+pub struct LinkPattern {
+    pub name: String,
+    pub kind: LinkKind,
+    regex:    Regex
+}
+
+impl LinkPattern {
+    pub fn new(
+        name: impl Into<String>,
+        kind: LinkKind,
+        regex: &str
+    ) -> Result<Self, regex::Error> {
+        Ok(Self {
+            name: name.into(),
+            kind,
+            regex: Regex::new(regex)?
+        })
+    }
+
+    fn links(&self, text: &str) -> Vec<Hyperlink> {
+        self.regex
+            .captures_iter(text)
+            .filter_map(|x| self.link(&x))
+            .collect()
+    }
+
+    fn link(&self, captures: &Captures) -> Option<Hyperlink> {
+        let link =
+            captures.name("link").or_else(|| captures.get(0))?;
+        match self.kind {
+            LinkKind::File => Some(Hyperlink::File {
+                range:  link.range(),
+                src:    captures.name("path")?.as_str().to_string(),
+                line:   captures
+                    .name("line")?
+                    .as_str()
+                    .parse()
+                    .ok()?,
+                column: captures
+                    .name("column")
+                    .and_then(|x| x.as_str().parse().ok())
+            }),
+            LinkKind::Url => {
+                // sentence punctuation right after a url
+                let url = link
+                    .as_str()
+                    .trim_end_matches(['.', ',', ';', ':', '!', '?']);
+                Some(Hyperlink::Url {
+                    range: link.start()..link.start() + url.len(),
+                    url:   url.to_string()
+                })
+            }
+        }
+    }
+}
+
+/// Finds hyperlinks in plain output, e.g. stderr, where there are no
+/// spans as in cargo json messages.
+///
+/// Patterns are tried in order; a match overlapping an existing link
+/// or an earlier match is dropped.
+#[derive(Clone, Debug)]
+pub struct Linkifier {
+    patterns: Vec<LinkPattern>
+}
+
+/// `path:line:column`, with an optional windows drive
+const LOCATION: &str = concat!(
+    r#"(?P<link>(?P<path>(?:[A-Za-z]:)?[^\s:'"`]+)"#,
+    r":(?P<line>\d+):(?P<column>\d+))"
+);
+
+/// `   at path:line[:column]` of a backtrace
+const FRAME: &str = concat!(
+    r"(?m)^\s+at (?P<link>(?P<path>(?:[A-Za-z]:)?[^\s:]+)",
+    r":(?P<line>\d+)(?::(?P<column>\d+))?)"
+);
+
+impl Default for Linkifier {
+    /// panic locations, backtrace frames, `--> path:line:col` and
+    /// urls
+    fn default() -> Self {
+        let patterns = [
+            (
+                "diagnostic",
+                LinkKind::File,
+                format!(r"-->\s*{LOCATION}")
+            ),
+            (
+                "panic",
+                LinkKind::File,
+                format!(r"panicked at (?:'.*', )?{LOCATION}")
+            ),
+            ("backtrace", LinkKind::File, FRAME.to_string()),
+            (
+                "url",
+                LinkKind::Url,
+                r#"https?://[^\s<>"'`()\[\]{}]+"#.to_string()
+            )
+        ];
+        Self {
+            patterns: patterns
+                .into_iter()
+                .filter_map(|(name, kind, regex)| {
+                    LinkPattern::new(name, kind, &regex)
+                        .map_err(|err| error!("{name}: {err:?}"))
+                        .ok()
+                })
+                .collect()
+        }
+    }
+}
+
+impl Linkifier {
+    /// a linkifier without any pattern
+    pub fn empty() -> Self {
+        Self { patterns: vec![] }
+    }
+
+    pub fn with_pattern(mut self, pattern: LinkPattern) -> Self {
+        self.patterns.push(pattern);
+        self
+    }
+
+    pub fn patterns(&self) -> &[LinkPattern] {
+        &self.patterns
+    }
+
+    /// Add the links found in the text to its hyperlinks.
+    pub fn linkify(&self, text: &mut StyledText) {
+        let links = self.links(
+            text.styled_text.text.as_str(),
+            text.hyperlink.iter().map(|x| x.range()).collect()
+        );
+        text.hyperlink.extend(links);
+        text.hyperlink.sort_by_key(|x| x.range().start);
+    }
+
+    /// The links in `text` not overlapping `taken`.
+    pub fn links(
+        &self,
+        text: &str,
+        mut taken: Vec<Range<usize>>
+    ) -> Vec<Hyperlink> {
+        let mut links = vec![];
+        for pattern in &self.patterns {
+            for link in pattern.links(text) {
+                let range = link.range();
+                if range.is_empty()
+                    || taken.iter().any(|x| {
+                        x.start < range.end && range.start < x.end
+                    })
+                {
+                    continue;
+                }
+                taken.push(range);
+                links.push(link);
+            }
+        }
+        links
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// range, path, line, column
+    type FileLink<'a> = (Range<usize>, &'a str, usize, Option<usize>);
+
+    fn file(link: &Hyperlink) -> FileLink<'_> {
+        match link {
+            Hyperlink::File {
+                range,
+                src,
+                line,
+                column
+            } => (range.clone(), src.as_str(), *line, *column),
+            Hyperlink::Url { .. } => panic!("not a file: {link:?}")
+        }
+    }
+
+    /// (range, url) of an url link
+    fn url(link: &Hyperlink) -> (Range<usize>, &str) {
+        match link {
+            Hyperlink::Url { range, url } => (range.clone(), url),
+            Hyperlink::File { .. } => panic!("not an url: {link:?}")
+        }
+    }
+
+    /// the range of the first `part` in `text`
+    fn range_of(text: &str, part: &str) -> Range<usize> {
+        let start = text.find(part).unwrap();
+        start..start + part.len()
+    }
+
+    fn default_links(text: &str) -> Vec<Hyperlink> {
+        Linkifier::default().links(text, vec![])
+    }
+
+    #[test]
+    fn test_panic() {
+        let text = "thread 'main' panicked at src/main.rs:4:5:";
+        let links = default_links(text);
+        assert_eq!(1, links.len());
+        assert_eq!(
+            (
+                range_of(text, "src/main.rs:4:5"),
+                "src/main.rs",
+                4,
+                Some(5)
+            ),
+            file(&links[0])
+        );
+
+        let text =
+            "thread 'main' panicked at 'boom: x', src/lib.rs:10:9";
+        let links = default_links(text);
+        assert_eq!(1, links.len());
+        assert_eq!(
+            (
+                range_of(text, "src/lib.rs:10:9"),
+                "src/lib.rs",
+                10,
+                Some(9)
+            ),
+            file(&links[0])
+        );
+    }
+
+    #[test]
+    fn test_backtrace() {
+        let text = "   0: app::main\n      at ./src/main.rs:4:5\n   \
+                    1: core::panicking::panic\n      at \
+                    /rustc/abc/library/core/src/panicking.rs:72";
+        let links = default_links(text);
+        assert_eq!(2, links.len());
+        assert_eq!(
+            (
+                range_of(text, "./src/main.rs:4:5"),
+                "./src/main.rs",
+                4,
+                Some(5)
+            ),
+            file(&links[0])
+        );
+        let path = "/rustc/abc/library/core/src/panicking.rs";
+        assert_eq!(
+            (range_of(text, &format!("{path}:72")), path, 72, None),
+            file(&links[1])
+        );
+    }
+
+    #[test]
+    fn test_diagnostic() {
+        let text = "  --> src/views/panel.rs:12:34";
+        let links = default_links(text);
+        assert_eq!(1, links.len());
+        assert_eq!(
+            (
+                range_of(text, "src/views/panel.rs:12:34"),
+                "src/views/panel.rs",
+                12,
+                Some(34)
+            ),
+            file(&links[0])
+        );
+
+        let text = r"  --> C:\Users\me\app\src\main.rs:2:9";
+        let links = default_links(text);
+        assert_eq!(1, links.len());
+        assert_eq!(
+            (
+                range_of(text, r"C:\Users\me\app\src\main.rs:2:9"),
+                r"C:\Users\me\app\src\main.rs",
+                2,
+                Some(9)
+            ),
+            file(&links[0])
+        );
+    }
+
+    #[test]
+    fn test_url() {
+        let text = "see https://doc.rust-lang.org/book/ch01.html.";
+        let links = default_links(text);
+        assert_eq!(1, links.len());
+        let expected = "https://doc.rust-lang.org/book/ch01.html";
+        assert_eq!(
+            (range_of(text, expected), expected),
+            url(&links[0])
+        );
+
+        let text = "(https://example.com/a?b=c), https://x.org/y!";
+        let links = default_links(text);
+        assert_eq!(2, links.len());
+        let expected = "https://example.com/a?b=c";
+        assert_eq!(
+            (range_of(text, expected), expected),
+            url(&links[0])
+        );
+        let expected = "https://x.org/y";
+        assert_eq!(
+            (range_of(text, expected), expected),
+            url(&links[1])
+        );
+    }
+
+    #[test]
+    fn test_overlap() {
+        let text = "  --> src/main.rs:2:9 done";
+        // an existing link, e.g. from a cargo json span, wins
+        let taken = vec![range_of(text, "main")];
+        assert!(Linkifier::default().links(text, taken).is_empty());
+        let taken = vec![range_of(text, "done")];
+        assert_eq!(1, Linkifier::default().links(text, taken).len());
+
+        // an earlier pattern wins
+        let word =
+            LinkPattern::new("word", LinkKind::Url, r"\S+").unwrap();
+        let linkifier = Linkifier::default().with_pattern(word);
+        let links = linkifier.links(text, vec![]);
+        assert_eq!(3, links.len());
+        let location = range_of(text, "src/main.rs:2:9");
+        assert_eq!(location, file(&links[0]).0);
+        assert_eq!((range_of(text, "-->"), "-->"), url(&links[1]));
+        assert_eq!((range_of(text, "done"), "done"), url(&links[2]));
+    }
+}
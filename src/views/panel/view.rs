@@ -1,21 +1,34 @@
-use crate::views::panel::data::DocManager;
+use crate::views::{
+    panel::{
+        CopyFormat, FollowTail, GutterLayout, HoverTip, Hyperlink,
+        PanelTheme, SimpleDoc, VisualDecoration, VisualHyperlink,
+        data::DocManager
+    },
+    tree_with_panel::data::{Level, VisualLine}
+};
+use chrono::{DateTime, Local};
+use doc::lines::layout::TextLayout;
 use floem::{
     Renderer, View, ViewId,
     context::{PaintCx, StyleCx},
-    event::{Event, EventListener},
+    event::{Event, EventListener, EventPropagation},
     keyboard::Key,
-    kurbo::{Line, Point, Rect, Stroke},
+    kurbo::{BezPath, Circle, Line, Point, Rect, Stroke},
+    menu::{Menu, MenuItem},
     peniko::Color,
     prelude::Decorators,
-    reactive::SignalGet,
+    reactive::{Memo, SignalGet, SignalWith, create_memo},
     style::{CursorStyle, Style},
     taffy::NodeId,
-    views::scroll
+    views::{label, scroll, stack}
 };
 use log::error;
+use std::ops::Range;
 
 pub fn panel(doc: DocManager) -> impl View {
     let hover_hyperlink = doc.with_untracked(|x| x.hover_hyperlink);
+    let theme =
+        create_memo(move |_| doc.with(|x| x.style.theme.clone()));
     let view = doc
         .on_event_cont(EventListener::PointerDown, move |event| {
             if let Event::PointerDown(pointer_event) = event {
@@ -41,6 +54,9 @@ pub fn panel(doc: DocManager) -> impl View {
                 }
             }
         })
+        .on_event_cont(EventListener::PointerLeave, move |_| {
+            doc.update(|x| x.pointer_leave());
+        })
         .on_event_cont(EventListener::PointerUp, move |event| {
             if let Event::PointerUp(pointer_event) = event {
                 let rs = doc.try_update(|x| {
@@ -53,6 +69,23 @@ pub fn panel(doc: DocManager) -> impl View {
                 }
             }
         })
+        .on_event(EventListener::KeyDown, move |event| {
+            if let Event::KeyDown(key_event) = event {
+                doc.update(|x| x.key_modifiers(key_event, true));
+                match doc.try_update(|x| x.key_down(key_event)) {
+                    Some(Ok(true)) => return EventPropagation::Stop,
+                    Some(Err(err)) => error!("{err:?}"),
+                    None => error!("doc try update key down fail"),
+                    _ => ()
+                }
+            }
+            EventPropagation::Continue
+        })
+        .on_event_cont(EventListener::KeyUp, move |event| {
+            if let Event::KeyUp(key_event) = event {
+                doc.update(|x| x.key_modifiers(key_event, false));
+            }
+        })
         .keyboard_navigable()
         .on_key_down(
             Key::Character("c".into()),
@@ -65,6 +98,7 @@ pub fn panel(doc: DocManager) -> impl View {
                 });
             }
         )
+        .context_menu(move || context_menu(doc))
         .style(move |x| {
             let hover_hyperlink = hover_hyperlink.get();
             x.apply_if(hover_hyperlink.is_some(), |x| {
@@ -72,14 +106,7 @@ pub fn panel(doc: DocManager) -> impl View {
             })
         });
     let handle_thickness = 12.0;
-    scroll(view)
-        .on_event_cont(EventListener::PointerDown, move |x| {
-            if let Event::PointerDown(_) = x {
-                doc.update(|x| {
-                    x.auto_scroll = false;
-                });
-            }
-        })
+    let scroll_view = scroll(view)
         .on_scroll(move |viewport| {
             doc.update(|x| x.update_viewport_by_scroll(viewport));
         })
@@ -87,12 +114,190 @@ pub fn panel(doc: DocManager) -> impl View {
             s.handle_thickness(handle_thickness).shrink_to_fit()
         })
         .style(move |x| {
-            x.border(1.0)
+            let theme = theme.get();
+            x.size_full()
+                .background(theme.background)
+                .border_color(theme.gutter_fg)
+                .border(1.0)
                 .padding_right(handle_thickness)
                 // .margin_left(6.0)
                 .padding_left(6.0)
                 .padding_bottom(handle_thickness)
+        });
+    stack((
+        scroll_view,
+        hover_tip(doc, theme),
+        jump_to_latest(doc, theme, handle_thickness)
+    ))
+}
+
+/// the target of the hovered hyperlink, with the source lines while
+/// ctrl is held
+fn hover_tip(doc: DocManager, theme: Memo<PanelTheme>) -> impl View {
+    let (hover_tip, font_family) = doc.with_untracked(|x| {
+        (x.hover_tip, x.style.font_family.clone())
+    });
+    label(move || {
+        hover_tip.with(|tip| match tip {
+            Some(HoverTip {
+                target,
+                preview: Some(preview),
+                ..
+            }) => format!("{target}\n{preview}"),
+            Some(tip) => tip.target.clone(),
+            None => String::new()
+        })
+    })
+    .style(move |x| {
+        let theme = theme.get();
+        // below the pointer, past the border and padding of the
+        // scroll view
+        let position =
+            hover_tip.with(|x| x.as_ref().map(|x| x.position));
+        x.absolute()
+            .apply_opt(position, |x, position| {
+                x.inset_left(position.x + 7.0)
+                    .inset_top(position.y + 21.0)
+            })
+            .padding_horiz(6.0)
+            .padding_vert(3.0)
+            .border(1.0)
+            .border_radius(4.0)
+            .border_color(theme.gutter_fg)
+            .color(theme.foreground)
+            .background(theme.gutter_bg)
+            .font_family(font_family.clone())
+            .apply_if(position.is_none(), |x| x.hide())
+    })
+}
+
+/// shown while following is paused, resumes it when clicked
+fn jump_to_latest(
+    doc: DocManager,
+    theme: Memo<PanelTheme>,
+    inset: f64
+) -> impl View {
+    let follow_tail = doc.follow_tail;
+    label(move || match follow_tail.get() {
+        FollowTail::Paused { new_lines, .. } if new_lines > 0 => {
+            format!("Jump to latest ({new_lines} new lines)")
+        },
+        _ => "Jump to latest".to_string()
+    })
+    .on_click_stop(move |_| {
+        doc.update(|x| x.set_follow_tail(true));
+    })
+    .style(move |x| {
+        let following = follow_tail.get() == FollowTail::Following;
+        let theme = theme.get();
+        x.absolute()
+            .inset_bottom(inset + 8.0)
+            .inset_right(inset + 8.0)
+            .padding_horiz(8.0)
+            .padding_vert(4.0)
+            .border(1.0)
+            .border_radius(4.0)
+            .border_color(theme.gutter_fg)
+            .color(theme.foreground)
+            .background(theme.gutter_bg)
+            .cursor(CursorStyle::Pointer)
+            .apply_if(following, |x| x.hide())
+    })
+}
+
+fn context_menu(doc: DocManager) -> Menu {
+    let (has_selection, hyperlink, crate_id, following) = doc
+        .with_untracked(|x| {
+            let hyperlink =
+                x.hyperlink_at_pointer().unwrap_or_else(|err| {
+                    error!("{err:?}");
+                    None
+                });
+            let crate_id =
+                x.crate_display_at_pointer().unwrap_or_else(|err| {
+                    error!("{err:?}");
+                    None
+                });
+            (
+                x.cursor.region().is_some(),
+                hyperlink,
+                crate_id,
+                x.is_following()
+            )
+        });
+    let copy_as = |format: CopyFormat| {
+        move || {
+            doc.with_untracked(|x| {
+                if let Err(err) = x.copy_select_as(format) {
+                    error!("{err:?}");
+                }
+            })
+        }
+    };
+    let copy_diagnostic = move || {
+        doc.with_untracked(|x| {
+            let format = CopyFormat::PlainText;
+            if let Err(err) = x.copy_diagnostic(format) {
+                error!("{err:?}");
+            }
+        })
+    };
+    let mut menu = Menu::new("")
+        .entry(
+            MenuItem::new("Copy")
+                .enabled(has_selection)
+                .action(copy_as(CopyFormat::PlainText))
+        )
+        .entry(
+            Menu::new("Copy as…")
+                .entry(
+                    MenuItem::new("ANSI")
+                        .enabled(has_selection)
+                        .action(copy_as(CopyFormat::Ansi))
+                )
+                .entry(
+                    MenuItem::new("Diagnostic")
+                        .action(copy_diagnostic)
+                )
+        )
+        .entry(MenuItem::new("Select All").action(move || {
+            doc.update(|x| {
+                if let Err(err) = x.select_all() {
+                    error!("{err:?}");
+                }
+            })
+        }))
+        .entry(
+            MenuItem::new("Clear")
+                .action(move || doc.update(|x| x.clear()))
+        );
+    if let Some(link) = hyperlink {
+        let title = match link {
+            Hyperlink::File { .. } => "Open File",
+            Hyperlink::Url { .. } => "Open Docs"
+        };
+        menu = menu.separator().entry(MenuItem::new(title).action(
+            move || doc.with_untracked(|x| x.open_hyperlink(&link))
+        ));
+    }
+    if let Some(id) = crate_id {
+        menu = menu.separator().entry(
+            MenuItem::new("Filter to this crate").action(move || {
+                let id = id.clone();
+                doc.update(|x| x.update_display(id))
+            })
+        );
+    }
+    menu.separator().entry(
+        MenuItem::new(if following {
+            "Pause following"
+        } else {
+            "Follow new output"
         })
+        .action(move || {
+            doc.update(|x| x.set_follow_tail(!following))
+        })
+    )
 }
 
 #[allow(dead_code)]
@@ -155,22 +360,44 @@ impl View for DocManager {
 
     fn paint(&mut self, cx: &mut PaintCx) {
         // debug!("paint");
-        let (viewport, lines, position_of_cursor, selections, style) =
-            self.with_untracked(|x| {
-                (
-                    x.viewport,
-                    x.viewport_lines(),
-                    x.position_of_cursor(),
-                    x.select_of_cursor(),
-                    x.style.clone()
-                )
-            });
+        let (
+            viewport,
+            lines,
+            position_of_cursor,
+            selections,
+            style,
+            gutter,
+            started
+        ) = self.with_untracked(|x| {
+            (
+                x.viewport,
+                x.viewport_lines(),
+                x.position_of_cursor(),
+                x.select_of_cursor(),
+                x.style.clone(),
+                x.gutter_layout(),
+                x.lines.started
+            )
+        });
+        let text_x = gutter.width();
+        let theme = &style.theme;
+        for line_info in &lines {
+            if let Some(tint) = theme.level_tint(line_info.level) {
+                let rect = Rect::new(
+                    viewport.x0 + text_x,
+                    line_info.pos_y,
+                    viewport.x1,
+                    line_info.pos_y + line_info.text.size().height
+                );
+                cx.fill(&rect, tint, 0.0);
+            }
+        }
         match selections {
             Ok(rects) => {
                 for rect in rects {
                     let rect = viewport.intersect(rect);
                     if !rect.is_zero_area() {
-                        cx.fill(&rect, style.selection_bg, 0.0);
+                        cx.fill(&rect, theme.selection_bg, 0.0);
                     }
                 }
             },
@@ -183,7 +410,7 @@ impl View for DocManager {
             Ok(Some(rect)) => {
                 let rect = viewport.intersect(rect);
                 if !rect.is_zero_area() {
-                    cx.fill(&rect, Color::BLACK, 0.0);
+                    cx.fill(&rect, theme.caret, 0.0);
                 }
             },
             Err(err) => {
@@ -191,24 +418,221 @@ impl View for DocManager {
             },
             Ok(None) => {}
         }
-        for line_info in lines {
+        for line_info in &lines {
             let y = line_info.pos_y;
             // debug!("line_index={} y={y} ", line_info.line_index);
-            paint_extra_style(cx, &line_info.hyperlinks);
+            paint_decorations(
+                cx,
+                &line_info.decorations,
+                viewport,
+                text_x,
+                style.line_height,
+                theme.foreground
+            );
+            paint_extra_style(cx, &line_info.hyperlinks, text_x);
             cx.draw_text_with_layout(
                 line_info.text.layout_runs(),
-                Point::new(0.0, y)
+                Point::new(text_x, y)
             );
         }
-        // paint select
+        // the gutter stays at the left of the viewport, over the text
+        // scrolled below it
+        let x0 = viewport.x0;
+        let rect =
+            Rect::new(x0, viewport.y0, x0 + text_x, viewport.y1);
+        cx.fill(&rect, theme.gutter_bg, 0.0);
+        self.with_untracked(|doc| {
+            for line_info in &lines {
+                paint_gutter(
+                    cx, doc, &gutter, line_info, started, x0
+                );
+                if let Some(folded) = line_info.folded {
+                    let y = line_info.pos_y;
+                    paint_fold_chevron(
+                        cx,
+                        Rect::new(
+                            x0 + gutter.fold.start,
+                            y,
+                            x0 + gutter.fold.end,
+                            y + line_info.row_height
+                        ),
+                        folded,
+                        theme.gutter_fg
+                    );
+                }
+            }
+        });
     }
 }
 
 fn paint_extra_style(
     cx: &mut PaintCx,
-    extra_styles: &[(Point, Point, Color)]
+    extra_styles: &[VisualHyperlink],
+    text_x: f64
+) {
+    for link in extra_styles {
+        let width = if link.hovered { 1.5 } else { 0.5 };
+        cx.stroke(
+            &Line::new(
+                Point::new(link.start.x + text_x, link.start.y),
+                Point::new(link.end.x + text_x, link.end.y)
+            ),
+            &link.color,
+            &Stroke::new(width)
+        );
+    }
+}
+
+fn paint_decorations(
+    cx: &mut PaintCx,
+    decorations: &[VisualDecoration],
+    viewport: Rect,
+    text_x: f64,
+    line_height: f64,
+    color: Color
 ) {
-    for (start, end, color) in extra_styles {
-        cx.stroke(&Line::new(*start, *end), color, &Stroke::new(0.5));
+    for decoration in decorations {
+        match decoration {
+            VisualDecoration::Rule(y) => {
+                cx.stroke(
+                    &Line::new(
+                        Point::new(viewport.x0 + text_x, *y),
+                        Point::new(viewport.x1, *y)
+                    ),
+                    &color.multiply_alpha(0.3),
+                    &Stroke::new(1.0)
+                );
+            },
+            VisualDecoration::Snippet(rect, layouts) => {
+                let rect = rect.with_origin(Point::new(
+                    rect.x0 + text_x,
+                    rect.y0
+                ));
+                cx.fill(&rect, color.multiply_alpha(0.06), 0.0);
+                let padding = line_height / 4.0;
+                for (index, layout) in layouts.iter().enumerate() {
+                    let y = rect.y0
+                        + padding
+                        + index as f64 * line_height;
+                    cx.draw_text_with_layout(
+                        layout.layout_runs(),
+                        Point::new(rect.x0 + padding, y)
+                    );
+                }
+            }
+        }
     }
 }
+
+/// the gutter columns of a line, `x0` being the left of the gutter
+fn paint_gutter(
+    cx: &mut PaintCx,
+    doc: &SimpleDoc,
+    gutter: &GutterLayout,
+    line_info: &VisualLine,
+    started: Option<DateTime<Local>>,
+    x0: f64
+) {
+    let style = &doc.style;
+    let y = line_info.pos_y;
+    let column = |x: &Range<f64>| x0 + x.start..x0 + x.end;
+    if let Some(range) = &gutter.line_number {
+        let text = (line_info.line_index + 1).to_string();
+        let layout = doc.gutter_text_layout(&text);
+        paint_gutter_text(cx, &layout, column(range), y, true);
+    }
+    let Some((time, level)) = line_info.block_head else {
+        return;
+    };
+    if let (Some(range), Some(timestamp), Some(started)) =
+        (&gutter.timestamp, style.gutter.timestamp, started)
+    {
+        let text = timestamp.format(time, started);
+        let layout = doc.gutter_text_layout(&text);
+        paint_gutter_text(cx, &layout, column(range), y, false);
+    }
+    if let (Some(range), Some(color)) =
+        (&gutter.level, style.theme.level_color(level))
+    {
+        let range = column(range);
+        let bottom = y + style.line_height;
+        let rect = Rect::new(range.start, y, range.end, bottom);
+        paint_level_marker(cx, rect, level, color);
+    }
+}
+
+fn paint_gutter_text(
+    cx: &mut PaintCx,
+    layout: &TextLayout,
+    column: Range<f64>,
+    y: f64,
+    align_right: bool
+) {
+    let x = if align_right {
+        column.end - layout.size().width
+    } else {
+        column.start
+    };
+    cx.draw_text_with_layout(layout.layout_runs(), Point::new(x, y));
+}
+
+/// a dot for errors and passed tests, a triangle for warnings, a
+/// diamond for info/help/note, centered in `rect`
+fn paint_level_marker(
+    cx: &mut PaintCx,
+    rect: Rect,
+    level: Level,
+    color: Color
+) {
+    let center = rect.center();
+    let radius = rect.width().min(rect.height()) / 4.0;
+    let mut path = BezPath::new();
+    match level {
+        Level::None => return,
+        Level::Error | Level::Success => {
+            cx.fill(&Circle::new(center, radius), color, 0.0);
+            return;
+        },
+        Level::Warn => {
+            let bottom = center.y + radius * 0.8;
+            path.move_to((center.x, center.y - radius));
+            path.line_to((center.x + radius, bottom));
+            path.line_to((center.x - radius, bottom));
+        },
+        Level::Info | Level::Help | Level::Note => {
+            path.move_to((center.x, center.y - radius));
+            path.line_to((center.x + radius, center.y));
+            path.line_to((center.x, center.y + radius));
+            path.line_to((center.x - radius, center.y));
+        }
+    }
+    path.close_path();
+    cx.fill(&path, color, 0.0);
+}
+
+/// `>` when folded, `v` when unfolded, centered in `rect`
+fn paint_fold_chevron(
+    cx: &mut PaintCx,
+    rect: Rect,
+    folded: bool,
+    color: Color
+) {
+    let center = rect.center();
+    let half = rect.width().min(rect.height()) / 6.0;
+    let points = if folded {
+        [
+            Point::new(center.x - half / 2.0, center.y - half),
+            Point::new(center.x + half / 2.0, center.y),
+            Point::new(center.x - half / 2.0, center.y + half)
+        ]
+    } else {
+        [
+            Point::new(center.x - half, center.y - half / 2.0),
+            Point::new(center.x, center.y + half / 2.0),
+            Point::new(center.x + half, center.y - half / 2.0)
+        ]
+    };
+    let stroke = Stroke::new(1.0);
+    cx.stroke(&Line::new(points[0], points[1]), &color, &stroke);
+    cx.stroke(&Line::new(points[1], points[2]), &color, &stroke);
+}
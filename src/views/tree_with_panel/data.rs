@@ -1,25 +1,42 @@
 use crate::{
+    cargo,
     channel::{ExtChannel, create_signal_from_channel},
-    views::panel::{
-        DisplayId, DocManager, DocStyle, Hyperlink, TextSrc
+    process::ProcessRunner,
+    views::{
+        drag_line::SplitterConstraints,
+        panel::{
+            CargoPackage, Decoration, DisplayId, DocManager,
+            DocStyle, Grouping, Hyperlink, Segment, TaskId, TextSrc,
+            VisualDecoration, VisualHyperlink
+        }
     }
 };
 use ansi_to_style::TextStyle;
 use anyhow::Result;
+use chrono::{DateTime, Local};
 use doc::lines::layout::*;
 use floem::{
     ViewId,
-    kurbo::Point,
-    peniko::Color,
-    prelude::{RwSignal, SignalGet, SignalUpdate, VirtualVector},
+    ext_event::create_ext_action,
+    keyboard::{Key, KeyEvent, NamedKey},
+    prelude::{
+        RwSignal, SignalGet, SignalUpdate, SignalWith, VirtualVector
+    },
     reactive::{Scope, batch}
 };
 use lapce_xi_rope::Rope;
 use log::error;
+use serde::{Deserialize, Serialize};
 use std::{
+    cmp::Ordering,
+    collections::HashMap,
+    fs,
     future::Future,
     ops::{AddAssign, Range},
-    thread
+    path::Path,
+    rc::Rc,
+    thread,
+    time::{Duration, Instant}
 };
 
 pub fn ranges_overlap(
@@ -42,31 +59,187 @@ pub fn ranges_overlap(
 
 #[derive(Clone)]
 pub struct TreePanelData {
-    pub cx:         Scope,
-    pub node:       RwSignal<TreeNode>,
-    pub doc:        DocManager,
-    pub left_width: RwSignal<f64>
+    pub cx:               Scope,
+    pub node:             RwSignal<TreeNode>,
+    pub doc:              DocManager,
+    pub left_width:       RwSignal<f64>,
+    /// the sizes the splitter next to the tree allows
+    pub left_constraints: SplitterConstraints,
+    /// tasks started by `run_task`/`run_sync_task`, in start order
+    pub tasks:            RwSignal<Vec<TaskId>>,
+    /// the node under the keyboard cursor, follows
+    /// `SimpleDoc::update_display`
+    pub selected:         RwSignal<DisplayId>,
+    /// the typed prefix and the time of the last key
+    type_ahead:           RwSignal<(String, Instant)>
+}
+
+/// The layout of a `TreePanelData`, to reopen it the way the user
+/// left it. See `TreePanelData::save` and `TreePanelData::restore`.
+#[derive(
+    Clone, Debug, Default, PartialEq, Serialize, Deserialize,
+)]
+pub struct TreePanelState {
+    pub left_width: f64,
+    /// open or closed nodes, the others keep their default. Sorted,
+    /// so that saving the same layout writes the same file.
+    pub open:       Vec<(DisplayId, bool)>,
+    pub selected:   DisplayId,
+    /// the top of the panel viewport, if not following the output
+    pub scroll_y:   f64,
+    pub following:  bool
+}
+
+/// Keys typed further apart start a new type-ahead search.
+const TYPE_AHEAD_TIMEOUT: Duration = Duration::from_millis(800);
+
+/// A row of the tree, for keyboard navigation.
+#[derive(Clone, Debug)]
+pub struct VisibleNode {
+    pub depth:        usize,
+    pub data:         TreeNodeData,
+    pub has_children: bool
+}
+
+/// The status of a task, shown next to its node.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TaskStatus {
+    Running,
+    Succeeded,
+    Failed
 }
 
 impl TreePanelData {
     pub fn new(cx: Scope, doc_style: DocStyle) -> Self {
         let doc = DocManager::new(cx, ViewId::new(), doc_style);
-        let node = cx.create_rw_signal(TreeNode {
-            display_id: DisplayId::All,
-            cx,
-            children: vec![],
-            open: cx.create_rw_signal(true),
-            level: cx.create_rw_signal(Level::None)
-        });
+        let node = cx.create_rw_signal(TreeNode::root(cx));
         let left_width = cx.create_rw_signal(200.0);
+        let selected = cx.create_rw_signal(DisplayId::All);
+        let display = doc.display;
+        cx.create_effect(move |_| {
+            let id = display.get();
+            selected.set(id);
+        });
+        // the panel was cleared: no lines left to count
+        let cleared = doc.cleared;
+        cx.create_effect(move |last: Option<usize>| {
+            let count = cleared.get();
+            if last.is_some_and(|x| x != count) {
+                node.update(|x| x.reset());
+            }
+            count
+        });
         Self {
             cx,
             node,
             doc,
-            left_width
+            left_width,
+            left_constraints: SplitterConstraints::new(60.0, 800.0),
+            tasks: cx.create_rw_signal(vec![]),
+            selected,
+            type_ahead: cx
+                .create_rw_signal((String::new(), Instant::now()))
         }
     }
 
+    /// Up/Down move the selection, Left/Right collapse/expand or go
+    /// to the parent/first child, Enter shows the selected node in
+    /// the panel, and typing selects the next node whose head starts
+    /// with the typed text. Return `true` if the key was consumed.
+    pub fn key_down(&self, event: &KeyEvent) -> bool {
+        let nodes = self.node.with_untracked(|x| x.visible_nodes());
+        let selected = self.selected.get_untracked();
+        let Some(index) = nodes
+            .iter()
+            .position(|x| x.data.display_id == selected)
+            .or((!nodes.is_empty()).then_some(0))
+        else {
+            return false;
+        };
+        let node = &nodes[index];
+        let open = node.data.open;
+        let target = match &event.key.logical_key {
+            Key::Named(NamedKey::ArrowUp) => index.checked_sub(1),
+            Key::Named(NamedKey::ArrowDown) => {
+                (index + 1 < nodes.len()).then_some(index + 1)
+            },
+            Key::Named(NamedKey::ArrowLeft) => {
+                if node.has_children && open.get_untracked() {
+                    open.set(false);
+                    None
+                } else {
+                    nodes[..index]
+                        .iter()
+                        .rposition(|x| x.depth < node.depth)
+                }
+            },
+            Key::Named(NamedKey::ArrowRight) => {
+                if !node.has_children {
+                    None
+                } else if open.get_untracked() {
+                    Some(index + 1)
+                } else {
+                    open.set(true);
+                    None
+                }
+            },
+            Key::Named(NamedKey::Enter) => {
+                let id = node.data.display_id.clone();
+                self.doc.update(|x| x.update_display(id));
+                return true;
+            },
+            Key::Character(c)
+                if !event.modifiers.control()
+                    && !event.modifiers.alt() =>
+            {
+                self.type_ahead(c, &nodes, index)
+            },
+            _ => return false
+        };
+        if let Some(node) = target.and_then(|x| nodes.get(x)) {
+            self.selected.set(node.data.display_id.clone());
+        }
+        true
+    }
+
+    /// the index of the next node matching the typed prefix
+    fn type_ahead(
+        &self,
+        c: &str,
+        nodes: &[VisibleNode],
+        index: usize
+    ) -> Option<usize> {
+        let now = Instant::now();
+        let prefix = self
+            .type_ahead
+            .try_update(|(prefix, last)| {
+                if now.duration_since(*last) > TYPE_AHEAD_TIMEOUT {
+                    prefix.clear();
+                }
+                prefix.push_str(&c.to_lowercase());
+                *last = now;
+                prefix.clone()
+            })
+            .unwrap_or_default();
+        // a new search starts below the selection, a longer prefix
+        // may still match it
+        let start = if prefix.chars().count() == 1 {
+            index + 1
+        } else {
+            index
+        };
+        (0..nodes.len()).map(|x| (start + x) % nodes.len()).find(
+            |x| {
+                nodes[*x]
+                    .data
+                    .display_id
+                    .head()
+                    .to_lowercase()
+                    .starts_with(&prefix)
+            }
+        )
+    }
+
     pub fn run_with_async_task<F, Fut>(&self, f: F)
     where
         F: Fn(
@@ -77,25 +250,53 @@ impl TreePanelData {
             + Send
             + 'static,
         Fut: Future<Output = anyhow::Result<()>> {
-        let (read_signal, channel, send) =
-            create_signal_from_channel::<StyledText>(self.cx);
-        let data = self.clone();
-        self.cx.create_effect(move |_| {
-            if let Some(line) = read_signal.get() {
-                data.node.update(|x| {
-                    x.add_child(line.id.display_id(), line.level)
-                });
-                data.doc.update(|x| {
-                    if let Err(err) = x.append_lines(line) {
-                        error!("{err:?}");
-                    }
-                });
+        let (channel, send) = self.receive(None);
+        thread::spawn(|| {
+            if let Err(err) = async_main_run(channel, f) {
+                error!("{:?}", err);
             }
+            send(())
         });
+    }
+
+    /// Like `run_with_async_task`, as a task of its own: a top-level
+    /// node named `name` with a status, and its lines under
+    /// `DisplayId::Task`. Tasks run side by side.
+    pub fn run_task<F, Fut>(
+        &self,
+        name: impl Into<String>,
+        f: F
+    ) -> TaskId
+    where
+        F: Fn(
+                ExtChannel<
+                    crate::views::tree_with_panel::data::StyledText
+                >
+            ) -> Fut
+            + Send
+            + 'static,
+        Fut: Future<Output = anyhow::Result<()>> {
+        let (task, finished) = self.start_task(name.into());
+        let (channel, send) = self.receive(Some(task.clone()));
         thread::spawn(|| {
-            async_main_run(channel, f);
+            let rs = async_main_run(channel, f);
+            if let Err(err) = &rs {
+                error!("{:?}", err);
+            }
+            finished(rs.is_ok());
             send(())
         });
+        task
+    }
+
+    /// Run a command, e.g. `npm`, `make` or `pytest`, as a task
+    /// named after the runner, grouping its lines by the tags of the
+    /// runner.
+    pub fn run_process(&self, runner: ProcessRunner) -> TaskId {
+        self.run_task(runner.name.clone(), move |channel| {
+            let runner = runner.clone();
+            async move { runner.run(channel).await }
+        })
     }
 
     pub fn run_with_sync_task<F>(&self, f: F)
@@ -108,27 +309,251 @@ impl TreePanelData {
             + Sync
             + Send
             + 'static {
+        let (channel, send) = self.receive(None);
+        thread::spawn(move || {
+            if let Err(err) = f(channel) {
+                error!("{err:?}");
+            }
+            send(())
+        });
+    }
+
+    /// `run_with_sync_task` as a task of its own, see `run_task`
+    pub fn run_sync_task<F>(
+        &self,
+        name: impl Into<String>,
+        f: F
+    ) -> TaskId
+    where
+        F: Fn(
+                ExtChannel<
+                    crate::views::tree_with_panel::data::StyledText
+                >
+            ) -> anyhow::Result<()>
+            + Sync
+            + Send
+            + 'static {
+        let (task, finished) = self.start_task(name.into());
+        let (channel, send) = self.receive(Some(task.clone()));
+        thread::spawn(move || {
+            let rs = f(channel);
+            if let Err(err) = &rs {
+                error!("{err:?}");
+            }
+            finished(rs.is_ok());
+            send(())
+        });
+        task
+    }
+
+    /// Order the children of every node by `sort_by`.
+    pub fn set_sort_by(&self, sort_by: SortBy) {
+        self.node.update(|x| x.set_sort_by(sort_by));
+    }
+
+    /// The layout to reopen the tree and the panel with, see
+    /// `restore`.
+    pub fn save(&self) -> TreePanelState {
+        let mut open = HashMap::new();
+        self.node.with_untracked(|x| {
+            open.extend(x.remembered.clone());
+            x.collect_open(&mut open);
+        });
+        let (scroll_y, following) = self
+            .doc
+            .with_untracked(|x| (x.viewport.y0, x.is_following()));
+        let mut open: Vec<_> = open.into_iter().collect();
+        open.sort();
+        TreePanelState {
+            left_width: self.left_width.get_untracked(),
+            open,
+            selected: self.selected.get_untracked(),
+            scroll_y,
+            following
+        }
+    }
+
+    /// Reopen the layout of `save`. Nodes not added yet open or
+    /// close when they are, the panel scrolls once the output is
+    /// long enough.
+    pub fn restore(&self, state: TreePanelState) {
+        let TreePanelState {
+            left_width,
+            open,
+            selected,
+            scroll_y,
+            following
+        } = state;
+        // a collapsed tree stays collapsed
+        self.left_width.set(if left_width > 0.0 {
+            self.left_constraints.clamp(left_width)
+        } else {
+            0.0
+        });
+        let open = open.into_iter().collect();
+        self.node.update(|x| x.restore_open(open));
+        self.doc.update(|x| {
+            x.update_display(selected);
+            if !following {
+                x.set_follow_tail(false);
+                x.scroll_to_y_when_ready(scroll_y);
+            }
+        });
+    }
+
+    /// `save` as json to `path`
+    pub fn save_to(&self, path: impl AsRef<Path>) -> Result<()> {
+        let json = serde_json::to_string_pretty(&self.save())?;
+        fs::write(path, json)?;
+        Ok(())
+    }
+
+    /// `restore` from the json of `save_to`
+    pub fn restore_from(&self, path: impl AsRef<Path>) -> Result<()> {
+        let json = fs::read_to_string(path)?;
+        self.restore(serde_json::from_str(&json)?);
+        Ok(())
+    }
+
+    /// Show only the node of `id` in the tree, with the nodes above
+    /// and below it, and its lines in the panel.
+    pub fn show_only(&self, id: DisplayId) {
+        self.node.update(|x| x.filter.only = Some(id.clone()));
+        self.doc.update(|x| x.update_display(id));
+    }
+
+    /// Hide the node of `id` and the nodes below it, showing all
+    /// lines if its lines were shown.
+    pub fn hide(&self, id: DisplayId) {
+        if id.contains(&self.doc.display.get_untracked()) {
+            self.doc.update(|x| x.update_display(DisplayId::All));
+        }
+        self.node.update(|x| x.filter.hidden.push(id));
+    }
+
+    /// Undo `show_only` and `hide`.
+    pub fn show_all(&self) {
+        self.node.update(|x| x.filter = NodeFilter::default());
+    }
+
+    pub fn set_open_all(&self, open: bool) {
+        self.node.update(|x| x.set_open_all(open));
+    }
+
+    /// `cargo build -p package` as a task, see `TreeNode::package`
+    pub fn rebuild_crate(&self, package: &CargoPackage) -> TaskId {
+        self.run_cargo_on_crate("build", package)
+    }
+
+    /// `cargo clippy -p package` as a task
+    pub fn clippy_crate(&self, package: &CargoPackage) -> TaskId {
+        self.run_cargo_on_crate("clippy", package)
+    }
+
+    /// Run `cargo command` in the workspace of `package`, its output
+    /// resolved like the main build so it reaches the crate and file
+    /// nodes.
+    fn run_cargo_on_crate(
+        &self,
+        command: &str,
+        package: &CargoPackage
+    ) -> TaskId {
+        let name = format!("cargo {command} -p {}", package.name);
+        let manifest_path = package.manifest_path.display();
+        self.run_process(
+            ProcessRunner::new(name, "cargo")
+                .args([command])
+                .args(cargo::MESSAGE_FORMAT)
+                .args(["--manifest-path"])
+                .args([manifest_path.to_string()])
+                .args(["-p", package.name.as_str()])
+                .resolve(cargo::resolve)
+        )
+    }
+
+    /// Group the output with `grouping`, e.g. a `GroupBy`, rebuilding
+    /// the tree and the panel. Tasks keep their nodes.
+    pub fn set_grouping(&self, grouping: impl Grouping + 'static) {
+        let grouping: Rc<dyn Grouping> = Rc::new(grouping);
+        self.doc.update(|x| x.set_grouping(grouping));
+        self.doc.with_untracked(|doc| {
+            self.node.update(|node| {
+                node.reset();
+                for block in doc.lines.blocks() {
+                    let path =
+                        doc.lines.path(&block.text_src, block.level);
+                    node.add_child(
+                        path,
+                        block.level,
+                        block.end_line - block.start_line,
+                        block.task.as_ref(),
+                        block.text_src.package().as_ref()
+                    );
+                }
+            })
+        });
+    }
+
+    /// Add the node of a new task. The returned closure sets its
+    /// status when the task is done.
+    fn start_task(
+        &self,
+        name: String
+    ) -> (TaskId, impl FnOnce(bool) + Send + 'static) {
+        let task = TaskId {
+            index: self.tasks.with_untracked(|x| x.len()),
+            name
+        };
+        let status = self.cx.create_rw_signal(TaskStatus::Running);
+        self.tasks.update(|x| x.push(task.clone()));
+        self.node.update(|x| x.add_task(&task, status));
+        let finished = create_ext_action(self.cx, move |ok: bool| {
+            status.set(if ok {
+                TaskStatus::Succeeded
+            } else {
+                TaskStatus::Failed
+            });
+        });
+        (task, finished)
+    }
+
+    /// A channel whose lines are added to the tree and the panel.
+    fn receive(
+        &self,
+        task: Option<TaskId>
+    ) -> (ExtChannel<StyledText>, impl FnOnce(()) + Send + 'static)
+    {
         let (read_signal, channel, send) =
             create_signal_from_channel::<StyledText>(self.cx);
         let data = self.clone();
         self.cx.create_effect(move |_| {
             if let Some(line) = read_signal.get() {
-                data.node.update(|x| {
-                    x.add_child(line.id.display_id(), line.level)
+                let level = line.level;
+                let path = data.doc.with_untracked(|x| {
+                    x.lines.path(&line.id, level)
                 });
-                data.doc.update(|x| {
-                    if let Err(err) = x.append_lines(line) {
-                        error!("{err:?}");
-                    }
+                let package = line.id.package();
+                let lines = data.doc.try_update(|x| {
+                    x.append_task_lines(task.clone(), line)
                 });
+                match lines {
+                    // like `set_grouping`, a text without lines is
+                    // not counted
+                    Some(Ok(0)) | None => {},
+                    Some(Ok(lines)) => data.node.update(|x| {
+                        x.add_child(
+                            path,
+                            level,
+                            lines,
+                            task.as_ref(),
+                            package.as_ref()
+                        )
+                    }),
+                    Some(Err(err)) => error!("{err:?}")
+                }
             }
         });
-        thread::spawn(move || {
-            if let Err(err) = f(channel) {
-                error!("{err:?}");
-            }
-            send(())
-        });
+        (channel, send)
     }
 }
 
@@ -136,26 +561,39 @@ impl TreePanelData {
 async fn async_main_run<F, Fut>(
     channel: ExtChannel<StyledText>,
     f: F
-) where
+) -> anyhow::Result<()>
+where
     F: Fn(ExtChannel<StyledText>) -> Fut,
     Fut: Future<Output = anyhow::Result<()>> {
-    if let Err(err) = f(channel).await {
-        error!("{:?}", err);
-    }
+    f(channel).await
 }
 
 #[derive(Clone)]
 pub struct StyledLines {
-    pub text_src: TextSrc,
-    pub lines:    Vec<(String, Vec<TextStyle>, Vec<Hyperlink>)>
+    pub text_src:    TextSrc,
+    pub level:       Level,
+    pub lines:       Vec<(String, Vec<TextStyle>, Vec<Hyperlink>)>,
+    pub decorations: Vec<Decoration>,
+    /// the task the lines came from, see `TreePanelData::run_task`
+    pub task:        Option<TaskId>
 }
 
 #[derive(Clone, Debug)]
 pub struct VisualLine {
-    pub pos_y:      f64,
-    pub line_index: usize,
-    pub hyperlinks: Vec<(Point, Point, Color)>,
-    pub text:       TextLayout
+    /// the top of the text, below the decorations above it
+    pub pos_y:       f64,
+    pub row_height:  f64,
+    pub line_index:  usize,
+    pub hyperlinks:  Vec<VisualHyperlink>,
+    /// `Some(folded)` for the header of a multi-line diagnostic
+    pub folded:      Option<bool>,
+    pub decorations: Vec<VisualDecoration>,
+    /// arrival time and level of the `StyledText` if this is its
+    /// first line
+    pub block_head:  Option<(DateTime<Local>, Level)>,
+    /// the level of the `StyledText` the line belongs to
+    pub level:       Level,
+    pub text:        Rc<TextLayout>
 }
 
 #[derive(Clone)]
@@ -163,7 +601,8 @@ pub struct StyledText {
     pub id:          TextSrc,
     pub level:       Level,
     pub styled_text: ansi_to_style::TextWithStyle,
-    pub hyperlink:   Vec<Hyperlink>
+    pub hyperlink:   Vec<Hyperlink>,
+    pub decorations: Vec<Decoration>
 }
 
 impl StyledText {
@@ -234,7 +673,10 @@ impl StyledText {
         }
         Ok(StyledLines {
             text_src: self.id,
-            lines
+            level: self.level,
+            lines,
+            decorations: self.decorations,
+            task: None
         })
     }
 }
@@ -245,20 +687,196 @@ pub struct TreeNode {
     pub display_id: DisplayId,
     pub children:   Vec<TreeNode>,
     pub level:      RwSignal<Level>,
-    pub open:       RwSignal<bool>
+    pub open:       RwSignal<bool>,
+    /// `Some` for the node of a task
+    pub status:     Option<RwSignal<TaskStatus>>,
+    /// rolled up from the children
+    pub counts:     RwSignal<NodeCounts>,
+    /// the order of the children, the same in the whole tree
+    pub sort_by:    SortBy,
+    /// the nodes shown, only used on the root
+    pub filter:     NodeFilter,
+    /// the restored open state of nodes, applied when they are
+    /// added. Only used on the root.
+    pub remembered: HashMap<DisplayId, bool>,
+    /// the cargo package of a crate node, see `TextSrc::package`
+    pub package:    Option<CargoPackage>
+}
+
+/// The nodes shown in the tree, with their ancestors and
+/// descendants.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct NodeFilter {
+    /// only this node, the nodes above it and the nodes below it
+    pub only:   Option<DisplayId>,
+    /// not these nodes nor the nodes below them
+    pub hidden: Vec<DisplayId>
+}
+
+impl NodeFilter {
+    pub fn is_empty(&self) -> bool {
+        self.only.is_none() && self.hidden.is_empty()
+    }
+
+    pub fn shows(&self, id: &DisplayId) -> bool {
+        !self.hidden.iter().any(|x| x.contains(id))
+            && self
+                .only
+                .as_ref()
+                .is_none_or(|x| x.contains(id) || id.contains(x))
+    }
+}
+
+/// The lines added under a node by `TreeNode::add_child`.
+struct Add<'a> {
+    path:       &'a [Segment],
+    level:      Level,
+    lines:      usize,
+    task:       Option<&'a TaskId>,
+    package:    Option<&'a CargoPackage>,
+    remembered: &'a HashMap<DisplayId, bool>
+}
+
+/// The order of the children of a tree node.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SortBy {
+    /// as they first got a line
+    #[default]
+    Arrival,
+    Name,
+    /// the most severe level first
+    Severity,
+    /// the most errors, warnings and lines first
+    Count
+}
+
+impl SortBy {
+    pub const ALL: [SortBy; 4] = [
+        SortBy::Arrival,
+        SortBy::Name,
+        SortBy::Severity,
+        SortBy::Count
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            SortBy::Arrival => "Arrival",
+            SortBy::Name => "Name",
+            SortBy::Severity => "Severity",
+            SortBy::Count => "Count"
+        }
+    }
+
+    fn compare(&self, a: &TreeNode, b: &TreeNode) -> Ordering {
+        let name = |x: &TreeNode| x.display_id.head().to_lowercase();
+        let count = |x: &TreeNode| {
+            let counts = x.counts.get_untracked();
+            (counts.errors, counts.warnings, counts.lines)
+        };
+        match self {
+            SortBy::Arrival => Ordering::Equal,
+            SortBy::Name => name(a).cmp(&name(b)),
+            SortBy::Severity => (b.level.get_untracked() as u8)
+                .cmp(&(a.level.get_untracked() as u8))
+                .then_with(|| name(a).cmp(&name(b))),
+            SortBy::Count => count(b)
+                .cmp(&count(a))
+                .then_with(|| name(a).cmp(&name(b)))
+        }
+    }
 }
 
 #[derive(Clone, Debug)]
 pub struct TreeNodeData {
     pub display_id: DisplayId,
     pub open:       RwSignal<bool>,
-    pub level:      RwSignal<Level>
+    pub level:      RwSignal<Level>,
+    pub status:     Option<RwSignal<TaskStatus>>,
+    pub counts:     RwSignal<NodeCounts>,
+    pub package:    Option<CargoPackage>
+}
+
+/// Diagnostics and lines under a tree node.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct NodeCounts {
+    pub errors:   usize,
+    pub warnings: usize,
+    /// `info`, `help` and `note`
+    pub notes:    usize,
+    pub passed:   usize,
+    pub lines:    usize
 }
 
-#[derive(Clone, Debug, Copy)]
+impl NodeCounts {
+    pub fn add(&mut self, level: Level, lines: usize) {
+        match level {
+            Level::Error => self.errors += 1,
+            Level::Warn => self.warnings += 1,
+            Level::Info | Level::Help | Level::Note => {
+                self.notes += 1
+            },
+            Level::Success => self.passed += 1,
+            Level::None => {}
+        }
+        self.lines += lines;
+    }
+
+    /// `(3 errors, 12 warnings, 1 note, 40 passed)`, or the lines
+    /// if there are no diagnostics
+    pub fn badge(&self) -> String {
+        let plural = |count: usize, name: &str| {
+            if count == 1 {
+                format!("1 {name}")
+            } else {
+                format!("{count} {name}s")
+            }
+        };
+        let mut parts = vec![];
+        if self.errors > 0 {
+            parts.push(plural(self.errors, "error"));
+        }
+        if self.warnings > 0 {
+            parts.push(plural(self.warnings, "warning"));
+        }
+        if self.notes > 0 {
+            parts.push(plural(self.notes, "note"));
+        }
+        if self.passed > 0 {
+            parts.push(format!("{} passed", self.passed));
+        }
+        if parts.is_empty() && self.lines > 0 {
+            parts.push(plural(self.lines, "line"));
+        }
+        if parts.is_empty() {
+            String::new()
+        } else {
+            format!("({})", parts.join(", "))
+        }
+    }
+}
+
+/// Cargo's diagnostic levels and test outcomes, from the least to
+/// the most severe: a node shows the most severe level below it.
+#[derive(
+    Clone,
+    Debug,
+    Copy,
+    PartialEq,
+    Eq,
+    Hash,
+    PartialOrd,
+    Ord,
+    Serialize,
+    Deserialize,
+)]
 #[repr(u8)]
 pub enum Level {
     None,
+    /// a passed test or a finished step
+    Success,
+    Info,
+    Help,
+    Note,
     Warn,
     Error
 }
@@ -280,89 +898,248 @@ impl Level {
         // };
         // *self = new_level;
     }
-}
 
-impl TreeNodeData {
-    pub fn track_level_svg(&self) -> &'static str {
-        match self.level.get() {
-            Level::None => {
-                // empty.svg
-                r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 16 16" width="16" height="16"></svg>"#
-            },
-            Level::Warn | Level::Error => {
-                // warning.svg
-                r#"<svg width="16" height="16" viewBox="0 0 16 16" xmlns="http://www.w3.org/2000/svg" fill="currentColor"><path fill-rule="evenodd" clip-rule="evenodd" d="M7.56 1h.88l6.54 12.26-.44.74H1.44L1 13.26 7.56 1zM8 2.28L2.28 13H13.7L8 2.28zM8.625 12v-1h-1.25v1h1.25zm-1.25-2V6h1.25v4h-1.25z"/></svg>"#
-            }
+    /// the head of the node of the lines of the level
+    pub fn label(&self) -> &'static str {
+        match self {
+            Level::None => "Other",
+            Level::Success => "Passed",
+            Level::Info => "Info",
+            Level::Help => "Help",
+            Level::Note => "Notes",
+            Level::Warn => "Warnings",
+            Level::Error => "Errors"
         }
     }
+}
 
-    pub fn track_level_svg_color(&self) -> Option<Color> {
-        match self.level.get() {
-        Level::None => None,
-            Level::Warn => Some(Color::from_rgb8(255, 204, 102)),
-            Level::Error => Some(Color::from_rgb8(255, 153, 153))
+impl TreeNode {
+    /// the node of `DisplayId::All`, the root of the tree
+    pub fn root(cx: Scope) -> Self {
+        TreeNode {
+            display_id: DisplayId::All,
+            cx,
+            children: vec![],
+            open: cx.create_rw_signal(true),
+            level: cx.create_rw_signal(Level::None),
+            status: None,
+            counts: cx.create_rw_signal(NodeCounts::default()),
+            sort_by: SortBy::default(),
+            filter: NodeFilter::default(),
+            remembered: HashMap::new(),
+            package: None
         }
     }
-}
 
-impl TreeNode {
-    pub fn add_child(&mut self, id: DisplayId, level: Level) {
-        batch(|| self._add_child(id, level));
-    }
-
-    fn _add_child(&mut self, id: DisplayId, level: Level) {
-        // debug!("add_child {:?}", id);
-        match &id {
-            DisplayId::All => {},
-            DisplayId::Error | DisplayId::Crate { .. } => {
-                self.level.update(|x| x.update(level));
-                if let Some(item) = self
-                    .children
-                    .iter_mut()
-                    .find(|x| id == x.display_id)
-                {
-                    item.level.update(|x| x.update(level));
-                } else {
-                    self.children.push(TreeNode {
-                        cx:         self.cx,
-                        display_id: id,
-                        level:      self.cx.create_rw_signal(level),
-                        open:       self.cx.create_rw_signal(true),
-                        children:   vec![]
-                    })
-                }
-            },
-            DisplayId::CrateFile { crate_name, .. } => {
-                let crate_id = DisplayId::Crate {
-                    crate_name: crate_name.clone()
-                };
-                self.add_child(crate_id.clone(), level);
-                if let Some(carte_item) = self
-                    .children
-                    .iter_mut()
-                    .find(|x| crate_id == x.display_id)
-                {
-                    if let Some(item) = carte_item
+    /// Add `lines` of `level` under the node of `path`, creating the
+    /// missing nodes on the way and counting the lines in every one.
+    /// `task`: the namespace of the nodes, `path` is unscoped.
+    /// `package`: the cargo package of the crate in `path`, if any.
+    pub fn add_child(
+        &mut self,
+        path: Vec<Segment>,
+        level: Level,
+        lines: usize,
+        task: Option<&TaskId>,
+        package: Option<&CargoPackage>
+    ) {
+        batch(|| {
+            self.record(level, lines);
+            let remembered = std::mem::take(&mut self.remembered);
+            let add = Add {
+                path: &path,
+                level,
+                lines,
+                task,
+                package,
+                remembered: &remembered
+            };
+            match task {
+                None => self.insert(&add, 1),
+                Some(task) => {
+                    let task_id = DisplayId::All.in_task(task);
+                    if let Some(item) = self
                         .children
                         .iter_mut()
-                        .find(|x| id == x.display_id)
+                        .find(|x| task_id == x.display_id)
                     {
-                        item.level.update(|x| x.update(level));
-                    } else {
-                        carte_item.children.push(TreeNode {
-                            cx:         self.cx,
-                            display_id: id,
-                            level:      self
-                                .cx
-                                .create_rw_signal(level),
-                            open:       self
-                                .cx
-                                .create_rw_signal(false),
-                            children:   vec![]
-                        })
+                        item.record(level, lines);
+                        item.insert(&add, 1);
                     }
                 }
             }
+            self.remembered = remembered;
+        });
+    }
+
+    /// Record the lines in the child of `path[..depth]` and below it,
+    /// down to the node of `path`.
+    fn insert(&mut self, add: &Add, depth: usize) {
+        if depth > add.path.len() {
+            return;
+        }
+        let mut id = DisplayId::Path(add.path[..depth].to_vec());
+        if let Some(task) = add.task {
+            id = id.in_task(task);
+        }
+        // only the top-level groups start open
+        let open =
+            add.remembered.get(&id).copied().unwrap_or(depth == 1);
+        let child = self.child_mut(id, open);
+        if matches!(add.path[depth - 1], Segment::Crate(_))
+            && child.package.is_none()
+        {
+            child.package = add.package.cloned();
+        }
+        child.record(add.level, add.lines);
+        child.insert(add, depth + 1);
+    }
+
+    fn child_mut(&mut self, id: DisplayId, open: bool) -> &mut Self {
+        let index = match self
+            .children
+            .iter()
+            .position(|x| x.display_id == id)
+        {
+            Some(index) => index,
+            None => {
+                let mut child = self.new_child(id, Level::None);
+                child.open = self.cx.create_rw_signal(open);
+                self.children.push(child);
+                self.children.len() - 1
+            }
+        };
+        &mut self.children[index]
+    }
+
+    fn record(&self, level: Level, lines: usize) {
+        self.level.update(|x| x.update(level));
+        self.counts.update(|x| x.add(level, lines));
+    }
+
+    /// Drop all nodes but the ones of tasks, emptied.
+    fn reset(&mut self) {
+        self.children.retain(|x| x.status.is_some());
+        for child in &mut self.children {
+            child.children.clear();
+            child.level.set(Level::None);
+            child.counts.set(NodeCounts::default());
+        }
+        self.level.set(Level::None);
+        self.counts.set(NodeCounts::default());
+    }
+
+    pub fn set_sort_by(&mut self, sort_by: SortBy) {
+        self.sort_by = sort_by;
+        for child in &mut self.children {
+            child.set_sort_by(sort_by);
+        }
+    }
+
+    /// the children shown by `filter`, in the order of `sort_by`
+    fn visible_children(
+        &self,
+        filter: &NodeFilter
+    ) -> Vec<&TreeNode> {
+        let mut children: Vec<&TreeNode> = self
+            .children
+            .iter()
+            .filter(|x| filter.shows(&x.display_id))
+            .collect();
+        children.sort_by(|a, b| self.sort_by.compare(a, b));
+        children
+    }
+
+    /// Open or close all nodes below this one.
+    pub fn set_open_all(&self, open: bool) {
+        for child in &self.children {
+            if !child.children.is_empty() {
+                child.open.set(open);
+            }
+            child.set_open_all(open);
+        }
+    }
+
+    /// the top-level node of a task
+    pub fn add_task(
+        &mut self,
+        task: &TaskId,
+        status: RwSignal<TaskStatus>
+    ) {
+        let id = DisplayId::All.in_task(task);
+        let open = self.remembered.get(&id).copied().unwrap_or(true);
+        let mut node = self.new_child(id, Level::None);
+        node.open.set(open);
+        node.status = Some(status);
+        self.children.push(node);
+    }
+
+    /// the open state of this node and the nodes below it
+    fn collect_open(&self, open: &mut HashMap<DisplayId, bool>) {
+        open.insert(
+            self.display_id.clone(),
+            self.open.get_untracked()
+        );
+        for child in &self.children {
+            child.collect_open(open);
+        }
+    }
+
+    /// Open or close the nodes in `open`, now and when added later.
+    fn restore_open(&mut self, open: HashMap<DisplayId, bool>) {
+        self.apply_open(&open);
+        self.remembered = open;
+    }
+
+    fn apply_open(&self, open: &HashMap<DisplayId, bool>) {
+        if let Some(x) = open.get(&self.display_id) {
+            self.open.set(*x);
+        }
+        for child in &self.children {
+            child.apply_open(open);
+        }
+    }
+
+    /// the rows of the tree: this node and the visible nodes of its
+    /// children if it is open
+    pub fn visible_nodes(&self) -> Vec<VisibleNode> {
+        let mut nodes = vec![];
+        self.push_visible_nodes(0, &mut nodes, &self.filter);
+        nodes
+    }
+
+    fn push_visible_nodes(
+        &self,
+        depth: usize,
+        nodes: &mut Vec<VisibleNode>,
+        filter: &NodeFilter
+    ) {
+        let children = self.visible_children(filter);
+        nodes.push(VisibleNode {
+            depth,
+            data: self.to_data(),
+            has_children: !children.is_empty()
+        });
+        if self.open.get_untracked() {
+            for child in children {
+                child.push_visible_nodes(depth + 1, nodes, filter);
+            }
+        }
+    }
+
+    fn new_child(&self, display_id: DisplayId, level: Level) -> Self {
+        TreeNode {
+            cx: self.cx,
+            display_id,
+            level: self.cx.create_rw_signal(level),
+            open: self.cx.create_rw_signal(true),
+            children: vec![],
+            status: None,
+            counts: self.cx.create_rw_signal(NodeCounts::default()),
+            sort_by: self.sort_by,
+            filter: NodeFilter::default(),
+            remembered: HashMap::new(),
+            package: None
         }
     }
 
@@ -370,16 +1147,22 @@ impl TreeNode {
         TreeNodeData {
             display_id: self.display_id.clone(),
             open:       self.open,
-            level:      self.level
+            level:      self.level,
+            status:     self.status,
+            counts:     self.counts,
+            package:    self.package.clone()
         }
     }
 
-    fn total(&self) -> usize {
+    fn total(&self, filter: &NodeFilter) -> usize {
         if self.open.get() {
-            self.children.iter().fold(1, |mut total, x| {
-                total += x.total();
-                total
-            })
+            self.visible_children(filter).iter().fold(
+                1,
+                |mut total, x| {
+                    total += x.total(filter);
+                    total
+                }
+            )
         } else {
             1
         }
@@ -390,7 +1173,8 @@ impl TreeNode {
         min: usize,
         max: usize,
         index: &mut usize,
-        level: usize
+        level: usize,
+        filter: &NodeFilter
     ) -> Vec<(usize, usize, TreeNodeData)> {
         let mut children_data = Vec::new();
         if min <= *index && *index <= max {
@@ -400,9 +1184,14 @@ impl TreeNode {
         }
         index.add_assign(1);
         if self.open.get() {
-            for child in self.children.iter() {
-                let mut children =
-                    child.get_children(min, max, index, level + 1);
+            for child in self.visible_children(filter) {
+                let mut children = child.get_children(
+                    min,
+                    max,
+                    index,
+                    level + 1,
+                    filter
+                );
                 children_data.append(&mut children);
             }
         }
@@ -412,7 +1201,7 @@ impl TreeNode {
 
 impl VirtualVector<(usize, usize, TreeNodeData)> for TreeNode {
     fn total_len(&self) -> usize {
-        self.total()
+        self.total(&self.filter)
     }
 
     fn slice(
@@ -422,8 +1211,179 @@ impl VirtualVector<(usize, usize, TreeNodeData)> for TreeNode {
         let min = range.start;
         let max = range.end;
         let mut index = 0;
-        let children = self.get_children(min, max, &mut index, 0);
+        let children =
+            self.get_children(min, max, &mut index, 0, &self.filter);
         // debug!("min={min} max={max} {:?}", children);
         children.into_iter()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn crate_path(name: &str) -> Vec<Segment> {
+        vec![Segment::Crate(name.to_string())]
+    }
+
+    fn file_path(name: &str, file: &str) -> Vec<Segment> {
+        let mut path = crate_path(name);
+        path.push(Segment::File(file.to_string()));
+        path
+    }
+
+    #[test]
+    fn test_badge() {
+        let mut counts = NodeCounts::default();
+        assert_eq!("", counts.badge());
+        counts.add(Level::None, 1);
+        assert_eq!("(1 line)", counts.badge());
+        counts.add(Level::None, 3);
+        assert_eq!("(4 lines)", counts.badge());
+        counts.add(Level::Error, 5);
+        assert_eq!("(1 error)", counts.badge());
+        counts.add(Level::Warn, 1);
+        counts.add(Level::Warn, 1);
+        counts.add(Level::Note, 1);
+        counts.add(Level::Help, 1);
+        counts.add(Level::Success, 1);
+        assert_eq!(
+            "(1 error, 2 warnings, 2 notes, 1 passed)",
+            counts.badge()
+        );
+        assert_eq!(14, counts.lines);
+    }
+
+    #[test]
+    fn test_add_child_counts() {
+        let mut root = TreeNode::root(Scope::new());
+        let main = file_path("app", "src/main.rs");
+        root.add_child(main.clone(), Level::Warn, 3, None, None);
+        root.add_child(main, Level::Error, 2, None, None);
+        root.add_child(crate_path("app"), Level::None, 1, None, None);
+        root.add_child(crate_path("lib"), Level::Warn, 4, None, None);
+
+        let counts = |node: &TreeNode| {
+            let counts = node.counts.get_untracked();
+            (counts.errors, counts.warnings, counts.lines)
+        };
+        assert_eq!((1, 2, 10), counts(&root));
+        assert_eq!(Level::Error, root.level.get_untracked());
+        let [app, lib] = root.children.as_slice() else {
+            panic!("two crates expected");
+        };
+        let file = &app.children[0];
+        let app_id = DisplayId::Path(crate_path("app"));
+        assert_eq!(app_id, app.display_id);
+        assert_eq!((1, 1, 6), counts(app));
+        assert_eq!((1, 1, 5), counts(file));
+        assert_eq!(Level::Error, file.level.get_untracked());
+        assert_eq!((0, 1, 4), counts(lib));
+        assert_eq!(Level::Warn, lib.level.get_untracked());
+        // only the top-level groups start open
+        assert!(app.open.get_untracked());
+        assert!(!file.open.get_untracked());
+    }
+
+    #[test]
+    fn test_sort_by() {
+        let mut root = TreeNode::root(Scope::new());
+        for (name, level, lines) in [
+            ("beta", Level::Warn, 1),
+            ("Alpha", Level::Error, 1),
+            ("gamma", Level::None, 5),
+            ("delta", Level::Warn, 3),
+            ("delta", Level::Warn, 1)
+        ] {
+            let path = crate_path(name);
+            root.add_child(path, level, lines, None, None);
+        }
+
+        let mut order = |sort_by: SortBy| {
+            root.set_sort_by(sort_by);
+            root.visible_children(&NodeFilter::default())
+                .iter()
+                .map(|x| x.display_id.segment().unwrap().head())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            ["beta", "Alpha", "gamma", "delta"],
+            order(SortBy::Arrival).as_slice()
+        );
+        assert_eq!(
+            ["Alpha", "beta", "delta", "gamma"],
+            order(SortBy::Name).as_slice()
+        );
+        // the same level by name
+        assert_eq!(
+            ["Alpha", "beta", "delta", "gamma"],
+            order(SortBy::Severity).as_slice()
+        );
+        // errors, then warnings, then lines
+        assert_eq!(
+            ["Alpha", "delta", "beta", "gamma"],
+            order(SortBy::Count).as_slice()
+        );
+    }
+
+    #[test]
+    fn test_node_filter() {
+        let app = DisplayId::Path(crate_path("app"));
+        let main = DisplayId::Path(file_path("app", "src/main.rs"));
+        let lib = DisplayId::Path(crate_path("lib"));
+        assert!(NodeFilter::default().shows(&main));
+
+        // the node, its ancestors and descendants
+        let only = NodeFilter {
+            only:   Some(app.clone()),
+            hidden: vec![]
+        };
+        assert!(only.shows(&DisplayId::All));
+        assert!(only.shows(&app));
+        assert!(only.shows(&main));
+        assert!(!only.shows(&lib));
+
+        let hidden = NodeFilter {
+            only:   None,
+            hidden: vec![main.clone()]
+        };
+        assert!(hidden.shows(&app));
+        assert!(!hidden.shows(&main));
+        assert!(hidden.shows(&lib));
+        let below_main = DisplayId::Path(vec![
+            Segment::Crate("app".to_string()),
+            Segment::File("src/main.rs".to_string()),
+            Segment::Level(Level::Warn),
+        ]);
+        assert!(!hidden.shows(&below_main));
+    }
+
+    #[test]
+    fn test_state_round_trip() {
+        let task = TaskId {
+            index: 1,
+            name:  "cargo test".to_string()
+        };
+        let main = DisplayId::Path(file_path("app", "src/main.rs"));
+        let test = DisplayId::Path(vec![
+            Segment::Level(Level::Error),
+            Segment::Module("views".to_string()),
+            Segment::Tag("suite".to_string()),
+        ]);
+        let state = TreePanelState {
+            left_width: 240.5,
+            open:       vec![
+                (DisplayId::All, true),
+                (main.clone(), false),
+                (test.in_task(&task), true),
+            ],
+            selected:   main.in_task(&task),
+            scroll_y:   1380.0,
+            following:  false
+        };
+        let json = serde_json::to_string_pretty(&state).unwrap();
+        let restored: TreePanelState =
+            serde_json::from_str(&json).unwrap();
+        assert_eq!(state, restored);
+    }
+}
@@ -1,21 +1,33 @@
 use crate::views::{
-    drag_line::x_drag_line,
-    panel::panel,
-    svg_from_fn,
-    tree_with_panel::data::{Level, TreePanelData}
+    drag_line::{SplitDirection, splitter},
+    icon::{Icon, icon},
+    panel::{
+        CargoPackage, DisplayId, GroupBy, Segment, open_with_system,
+        panel
+    },
+    tree_with_panel::data::{
+        Level, SortBy, TaskStatus, TreePanelData
+    }
 };
 use floem::{
-    View,
+    Clipboard, View,
+    event::{Event, EventListener, EventPropagation},
+    kurbo::Rect,
+    menu::{Menu, MenuItem},
     prelude::{
-        Decorators, SignalGet, SignalUpdate, VirtualDirection,
-        VirtualItemSize, container, scroll, stack, virtual_stack
+        Decorators, SignalGet, SignalUpdate, SignalWith,
+        VirtualDirection, VirtualItemSize, container, scroll, stack,
+        virtual_stack
     },
+    reactive::create_memo,
     style::AlignItems,
-    views::static_label
+    views::{label, static_label}
 };
+use log::error;
 
 pub fn tree_with_panel(data: TreePanelData) -> impl View {
     let left_width = data.left_width;
+    let left_constraints = data.left_constraints;
     let doc = data.doc;
     stack((
         view_tree(data).style(move |x| {
@@ -27,14 +39,21 @@ pub fn tree_with_panel(data: TreePanelData) -> impl View {
                 .border_bottom(1.)
                 .border_right(1.0)
         }),
-        x_drag_line(left_width).style(move |s| {
-            s.width(6.0).height_full().margin_left(-6.0)
-        }),
+        splitter(SplitDirection::Left, left_width, left_constraints)
+            .style(move |s| {
+                s.width(6.0).height_full().margin_left(-6.0)
+            }),
         panel(doc).style(|x| x.flex_grow(1.).height_full())
     ))
 }
 fn view_tree(data: TreePanelData) -> impl View {
     let node = data.node;
+    let selected = data.selected;
+    let key_data = data.clone();
+    let menu_data = data.clone();
+    let doc = data.doc;
+    let theme =
+        create_memo(move |_| doc.with(|x| x.style.theme.clone()));
     scroll(
         virtual_stack(
             VirtualDirection::Vertical,
@@ -44,51 +63,238 @@ fn view_tree(data: TreePanelData) -> impl View {
             move |(_, retract, rw_data)| {
                 let id = rw_data.display_id.clone();
                 let level = rw_data.level;
-                let level_svg_color = rw_data.track_level_svg_color();
 
                 let click_data = rw_data.open;
-                stack(
-                    (container(svg_from_fn(move || if click_data.get() {
-                            r#"<svg width="16" height="16" viewBox="0 0 16 16" xmlns="http://www.w3.org/2000/svg" fill="currentColor"><path fill-rule="evenodd" clip-rule="evenodd" d="M7.976 10.072l4.357-4.357.62.618L8.284 11h-.618L3 6.333l.619-.618 4.357 4.357z"/></svg>"#
-                    } else {
-                        r#"<svg width="16" height="16" viewBox="0 0 16 16" xmlns="http://www.w3.org/2000/svg" fill="currentColor"><path fill-rule="evenodd" clip-rule="evenodd" d="M10.072 8.024L5.715 3.667l.618-.62L11 7.716v.618L6.333 13l-.618-.619 4.357-4.357z"/></svg>"#
-                    }.to_string()).style(move |s| {
-                        let size = 13.0;
-                            s.size(size, size)
-                    })).on_click_stop(move |_ | {
-                        click_data.update(move |x| {
-                            *x = !*x
-                        });
+                let status = rw_data.status;
+                let counts = rw_data.counts;
+                let selected_id = id.clone();
+                let menu_id = id.clone();
+                let package = rw_data.package.clone();
+                let menu_data = data.clone();
+                stack((
+                    container(
+                        icon(move || Icon::of_open(click_data.get()))
+                            .style(move |s| {
+                                let size = 13.0;
+                                s.size(size, size)
+                            })
+                    )
+                    .on_click_stop(move |_| {
+                        click_data.update(move |x| *x = !*x);
                     }),
-                    container(svg_from_fn(move || match level.get() {
-                        Level::None => {
-                            // empty.svg
-                            r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 16 16" width="16" height="16"></svg>"#
-                        }
-                        Level::Warn | Level::Error => {
-                            // warning.svg
-                            r#"<svg width="16" height="16" viewBox="0 0 16 16" xmlns="http://www.w3.org/2000/svg" fill="currentColor"><path fill-rule="evenodd" clip-rule="evenodd" d="M7.56 1h.88l6.54 12.26-.44.74H1.44L1 13.26 7.56 1zM8 2.28L2.28 13H13.7L8 2.28zM8.625 12v-1h-1.25v1h1.25zm-1.25-2V6h1.25v4h-1.25z"/></svg>"#
+                    container(
+                        icon(move || Icon::of_level(level.get()))
+                            .style(move |s| {
+                                let size = 13.0;
+                                if let Some(color) = theme
+                                    .get()
+                                    .level_color(level.get())
+                                {
+                                    s.size(size, size).color(color)
+                                } else {
+                                    s.size(size, size)
+                                }
+                            })
+                    ),
+                    static_label(rw_data.display_id.head())
+                        .style(move |x| {
+                            x.height(23.)
+                                .font_size(13.)
+                                .align_self(AlignItems::Start)
+                        })
+                        .on_click_stop(move |_| {
+                            let value = id.clone();
+                            data.doc.update(move |x| {
+                                x.update_display(value.clone());
+                            });
+                        }),
+                    label(move || counts.get().badge()).style(
+                        move |x| {
+                            x.height(23.)
+                                .font_size(11.)
+                                .margin_left(6.)
+                                .color(theme.get().gutter_fg)
                         }
-                    }.to_string()).style(move |s| {
-                        let size = 13.0;
-                        if let Some(color) = level_svg_color {
-                            s.size(size, size).color(color)
-                        } else {
-                            s.size(size, size)
-                        }
-                    })),
-                static_label(rw_data.display_id.head()).style(move |x| x.height(23.).font_size(13.).align_self(AlignItems::Start))
-                    .on_click_stop(move |_ | {
-                        let value = id.clone();
-                        data.doc.update(move |x| {
-                            x.update_display(value.clone());
-                        });
+                    ),
+                    container(
+                        icon(move || {
+                            status
+                                .map(|x| Icon::of_status(x.get()))
+                                .unwrap_or(Icon::Empty)
+                        })
+                        .style(move |s| {
+                            let theme = theme.get();
+                            let color = match status.map(|x| x.get())
+                            {
+                                Some(TaskStatus::Succeeded) => {
+                                    theme.level_color(Level::Success)
+                                },
+                                Some(TaskStatus::Failed) => {
+                                    theme.level_color(Level::Error)
+                                },
+                                _ => None
+                            }
+                            .unwrap_or(theme.gutter_fg);
+                            s.size(11., 11.).color(color)
+                        })
+                    )
+                    .style(move |x| {
+                        x.height(23.)
+                            .margin_left(6.)
+                            .items_center()
+                            .apply_if(status.is_none(), |x| x.hide())
                     })
-                )).style(move |x| x.margin_left(retract as f32 * 13.0))
-            },
+                ))
+                .context_menu(move || {
+                    node_menu(
+                        menu_data.clone(),
+                        menu_id.clone(),
+                        package.clone()
+                    )
+                })
+                .style(move |x| {
+                    let is_selected =
+                        selected.with(|x| *x == selected_id);
+                    x.padding_left(retract as f32 * 13.0)
+                        .width_full()
+                        .apply_if(is_selected, |x| {
+                            x.background(theme.get().selection_bg)
+                        })
+                })
+            }
         )
-            .style(|s| s.flex_col().min_width_full().padding(6.0)),
+        .style(|s| s.flex_col().min_width_full().padding(6.0))
     )
-        .style(|s| s.flex_grow(1.0).size_full())
-        .scroll_style(|s| s.shrink_to_fit())
+    .ensure_visible(move || {
+        let id = selected.get();
+        let index = node.with_untracked(|x| {
+            x.visible_nodes()
+                .iter()
+                .position(|x| x.data.display_id == id)
+        });
+        let y = index.unwrap_or_default() as f64 * 20.0 + 6.0;
+        Rect::new(0.0, y, 1.0, y + 20.0)
+    })
+    .keyboard_navigable()
+    .context_menu(move || tree_menu(menu_data.clone()))
+    .on_event(EventListener::KeyDown, move |event| {
+        if let Event::KeyDown(key_event) = event {
+            if key_data.key_down(key_event) {
+                return EventPropagation::Stop;
+            }
+        }
+        EventPropagation::Continue
+    })
+    .style(|s| s.flex_grow(1.0).size_full())
+    .scroll_style(|s| s.shrink_to_fit())
+}
+
+/// actions on the node of `id`, then the ones of the whole tree.
+/// `package`: the cargo package of a crate node.
+fn node_menu(
+    data: TreePanelData,
+    id: DisplayId,
+    package: Option<CargoPackage>
+) -> Menu {
+    let mut menu = Menu::new("");
+    if let Some(Segment::File(file)) = id.segment() {
+        let path = std::path::absolute(file)
+            .map(|x| x.display().to_string())
+            .unwrap_or_else(|_| file.clone());
+        let copied = path.clone();
+        menu = menu
+            .entry(MenuItem::new("Open file").action(move || {
+                if let Err(err) = open_with_system(&path) {
+                    error!("open {path} fail: {err:?}");
+                }
+            }))
+            .entry(MenuItem::new("Copy path").action(move || {
+                let path = copied.clone();
+                if let Err(err) = Clipboard::set_contents(path) {
+                    error!("{err:?}");
+                }
+            }))
+            .separator();
+    }
+    let (only_data, only_id) = (data.clone(), id.clone());
+    let (hide_data, hide_id) = (data.clone(), id.clone());
+    menu = menu
+        .entry(
+            MenuItem::new("Show only this")
+                .action(move || only_data.show_only(only_id.clone()))
+        )
+        .entry(
+            MenuItem::new("Hide this")
+                .action(move || hide_data.hide(hide_id.clone()))
+        );
+    if data.node.with_untracked(|x| !x.filter.is_empty()) {
+        let data = data.clone();
+        menu = menu.entry(
+            MenuItem::new("Show all").action(move || data.show_all())
+        );
+    }
+    let (expand_data, collapse_data) = (data.clone(), data.clone());
+    menu = menu
+        .separator()
+        .entry(
+            MenuItem::new("Expand all")
+                .action(move || expand_data.set_open_all(true))
+        )
+        .entry(
+            MenuItem::new("Collapse all")
+                .action(move || collapse_data.set_open_all(false))
+        );
+    if let (Some(Segment::Crate(_)), Some(package)) =
+        (id.segment(), package)
+    {
+        let (build_data, build_package) =
+            (data.clone(), package.clone());
+        let (clippy_data, clippy_package) = (data.clone(), package);
+        menu = menu
+            .separator()
+            .entry(MenuItem::new("Rebuild this crate").action(
+                move || {
+                    build_data.rebuild_crate(&build_package);
+                }
+            ))
+            .entry(MenuItem::new("Run clippy on this crate").action(
+                move || {
+                    clippy_data.clippy_crate(&clippy_package);
+                }
+            ));
+    }
+    menu.separator()
+        .entry(sort_menu(data.clone()))
+        .entry(group_menu(data))
+}
+
+/// sorting and grouping of the tree
+fn tree_menu(data: TreePanelData) -> Menu {
+    Menu::new("")
+        .entry(sort_menu(data.clone()))
+        .entry(group_menu(data))
+}
+
+fn sort_menu(data: TreePanelData) -> Menu {
+    let mut sort = Menu::new("Sort by");
+    for sort_by in SortBy::ALL {
+        let data = data.clone();
+        sort = sort.entry(
+            MenuItem::new(sort_by.label())
+                .action(move || data.set_sort_by(sort_by))
+        );
+    }
+    sort
+}
+
+fn group_menu(data: TreePanelData) -> Menu {
+    let mut group = Menu::new("Group by");
+    for group_by in GroupBy::ALL {
+        let data = data.clone();
+        group = group.entry(
+            MenuItem::new(group_by.label())
+                .action(move || data.set_grouping(group_by))
+        );
+    }
+    group
 }